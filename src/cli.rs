@@ -0,0 +1,204 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+
+use crate::external::cartridge::LoadOptions;
+use crate::gb_config::{gb_config, LogFlags};
+use crate::model::Model;
+use crate::ppu::core::Renderer;
+
+pub const DEFAULT_CONFIG: &str = "Settings";
+
+pub fn app() -> App<'static, 'static> {
+  return App::new("gameboy-emulator")
+    .about("Game Boy emulator. Options given here override the settings file.")
+    .setting(AppSettings::SubcommandsNegateReqs)
+    .arg(Arg::with_name("rom")
+      .value_name("ROM")
+      .help("ROM to run (.gb, .gbc, or a .zip/.gz holding one)")
+      .required(true))
+    .arg(Arg::with_name("config")
+      .short("c")
+      .long("config")
+      .value_name("FILE")
+      .help("Settings file to read instead of Settings.toml"))
+    .arg(Arg::with_name("boot_rom")
+      .long("boot-rom")
+      .value_name("FILE")
+      .help("Run this boot rom before the cartridge"))
+    .arg(Arg::with_name("no_boot_rom")
+      .long("no-boot-rom")
+      .conflicts_with("boot_rom")
+      .help("Skip the boot rom and start from the model's post-boot state"))
+    .arg(Arg::with_name("model")
+      .short("m")
+      .long("model")
+      .value_name("MODEL")
+      .possible_values(&["dmg0", "dmg", "mgb", "sgb", "sgb2", "cgb"])
+      .help("Hardware revision"))
+    .arg(Arg::with_name("renderer")
+      .short("r")
+      .long("renderer")
+      .value_name("RENDERER")
+      .possible_values(&["scanline", "fifo"])
+      .help("Draw whole lines, or pixel by pixel for mid-line effects and exact mode 3 timing"))
+    .arg(Arg::with_name("debug")
+      .short("d")
+      .long("debug")
+      .help("Start the interactive debugger"))
+    .arg(Arg::with_name("no_debug")
+      .long("no-debug")
+      .conflicts_with("debug")
+      .help("Run without the debugger"))
+    .arg(Arg::with_name("breakpoint")
+      .short("b")
+      .long("breakpoint")
+      .value_name("ADDR")
+      .help("Initial debugger breakpoint, in hex"))
+    .arg(Arg::with_name("headless")
+      .long("headless")
+      .conflicts_with("debug")
+      .help("Run unthrottled without the debugger"))
+    .arg(Arg::with_name("frames")
+      .short("f")
+      .long("frames")
+      .value_name("N")
+      .help("Stop after N frames"))
+    .arg(Arg::with_name("log")
+      .short("l")
+      .long("log")
+      .value_name("FLAGS")
+      .help("Print these debug log categories, comma separated: all, tick, memory_save, memory_fetch, \
+             vram_save, decoding, snapshot, register, interrupt, rumble"))
+    .arg(Arg::with_name("strict")
+      .long("strict")
+      .help("Refuse ROMs with a bad logo or checksum"))
+    .arg(Arg::with_name("patch")
+      .short("p")
+      .long("patch")
+      .value_name("FILE")
+      .help("IPS/UPS/BPS patch to apply instead of the one next to the ROM"))
+    .arg(Arg::with_name("no_patch")
+      .long("no-patch")
+      .conflicts_with("patch")
+      .help("Don't apply patches found next to the ROM"))
+    .arg(Arg::with_name("entry")
+      .long("entry")
+      .value_name("NAME")
+      .help("File to load from a zip archive instead of the first ROM in it"))
+    .subcommand(SubCommand::with_name("info")
+      .about("Prints the cartridge header without running it")
+      .arg(Arg::with_name("rom").value_name("ROM").required(true))
+      .arg(Arg::with_name("json").long("json").help("Print as JSON")));
+}
+
+fn parse_number(value: &str, name: &str) -> Result<u64, String> {
+  let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+  return u64::from_str_radix(digits, 16).map_err(|_| format!("Invalid {} {}", name, value));
+}
+
+/// Loads the settings file picked on the command line, then applies every
+/// option given on top of it
+pub fn config(matches: &ArgMatches) -> Result<gb_config, String> {
+  let mut cfg = gb_config::new(matches.value_of("config").unwrap_or(DEFAULT_CONFIG))?;
+  apply_overrides(&mut cfg, matches)?;
+  return Ok(cfg);
+}
+
+pub fn apply_overrides(cfg: &mut gb_config, matches: &ArgMatches) -> Result<(), String> {
+  if let Some(path) = matches.value_of("boot_rom") {
+    cfg.boot_rom_enabled = true;
+    cfg.boot_rom_path = String::from(path);
+  }
+  if matches.is_present("no_boot_rom") {
+    cfg.boot_rom_enabled = false;
+  }
+  if let Some(name) = matches.value_of("model") {
+    cfg.model = Model::from_name(name).ok_or_else(|| format!("Unknown model {}", name))?;
+  }
+  if let Some(name) = matches.value_of("renderer") {
+    cfg.renderer = Renderer::from_name(name).ok_or_else(|| format!("Unknown renderer {}", name))?;
+  }
+  if matches.is_present("debug") {
+    cfg.debug_mode = true;
+  }
+  if matches.is_present("no_debug") {
+    cfg.debug_mode = false;
+  }
+  if let Some(addr) = matches.value_of("breakpoint") {
+    let addr = parse_number(addr, "breakpoint")?;
+    if addr > 0xFFFF {
+      return Err(format!("Breakpoint {:#x} is outside the address space", addr));
+    }
+    cfg.initial_breakpoint = addr as u16;
+  }
+  if matches.is_present("headless") {
+    cfg.headless = true;
+    cfg.debug_mode = false;
+  }
+  if let Some(frames) = matches.value_of("frames") {
+    cfg.frame_limit = Some(frames.parse().map_err(|_| format!("Invalid frame count {}", frames))?);
+  }
+  if let Some(list) = matches.value_of("log") {
+    cfg.logger_enabled = true;
+    cfg.log_flags = LogFlags::from_list(list)?;
+  }
+  if matches.is_present("strict") {
+    cfg.strict_header = true;
+  }
+  return Ok(());
+}
+
+pub fn load_options(matches: &ArgMatches) -> LoadOptions {
+  return LoadOptions {
+    entry: matches.value_of("entry").map(String::from),
+    patch: matches.value_of("patch").map(String::from),
+    auto_patch: !matches.is_present("no_patch"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn overridden(args: &[&str]) -> Result<gb_config, String> {
+    let mut argv = vec!["gameboy-emulator", "game.gb"];
+    argv.extend_from_slice(args);
+    let matches = app().get_matches_from_safe(argv).map_err(|e| e.message)?;
+    let mut cfg = gb_config { debug_mode: true, boot_rom_enabled: true, ..gb_config::default() };
+    apply_overrides(&mut cfg, &matches)?;
+    Ok(cfg)
+  }
+
+  #[test]
+  fn command_line_overrides_settings() {
+    let cfg = overridden(&["--no-boot-rom", "-m", "cgb", "-b", "0x150", "--frames", "60", "--log", "tick"]).unwrap();
+    assert!(!cfg.boot_rom_enabled);
+    assert_eq!(cfg.model, Model::CGB);
+    assert_eq!(cfg.initial_breakpoint, 0x150);
+    assert_eq!(cfg.frame_limit, Some(60));
+    assert!(cfg.logger_enabled);
+    assert!(cfg.log_flags.tick && !cfg.log_flags.memory_fetch);
+    assert!(cfg.debug_mode);
+
+    let cfg = overridden(&["--headless", "--boot-rom", "dmg.bin", "--renderer", "fifo"]).unwrap();
+    assert!(cfg.headless && !cfg.debug_mode);
+    assert_eq!(cfg.renderer, Renderer::Fifo);
+    assert_eq!(cfg.boot_rom_path, "dmg.bin");
+  }
+
+  #[test]
+  fn rejects_bad_values() {
+    assert!(overridden(&["-b", "zz"]).is_err());
+    assert!(overridden(&["-m", "gba"]).is_err());
+    assert!(overridden(&["--frames", "-1"]).is_err());
+    assert!(overridden(&["--debug", "--headless"]).is_err());
+    assert!(app().get_matches_from_safe(vec!["gameboy-emulator"]).is_err());
+  }
+
+  #[test]
+  fn info_does_not_need_the_run_arguments() {
+    let matches = app().get_matches_from_safe(vec!["gameboy-emulator", "info", "--json", "game.gb"]).unwrap();
+    let info = matches.subcommand_matches("info").unwrap();
+    assert_eq!(info.value_of("rom"), Some("game.gb"));
+    assert!(info.is_present("json"));
+  }
+}
@@ -1,15 +1,40 @@
 extern crate config;
 
 use crate::cpu::decoder::OpcodeDecoder;
+use crate::cpu::interrupt::Ime;
+use crate::cpu::opcode::Stack;
 use crate::{debug::logger::LoggableComponent};
 use crate::reg::{Registers};
 // use crate::opcode::OpCodes;
 use crate::mmu::VirtualMemory;
 use crate::debug::logger::{LogMessage,LogEvents};
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpuState {
+  Running,
+  /// Idle after HALT until an enabled interrupt is requested
+  Halted,
+  /// HALT with IME=0 and an interrupt already pending: the CPU keeps running
+  /// but fails to increment PC on the next opcode fetch
+  HaltBug,
+  /// Idle after STOP. Hardware wakes up on a joypad input edge; until the
+  /// joypad is modeled a new request of any enabled interrupt stands in for
+  /// it, even when its IF bit is still set from before the STOP.
+  Stopped,
+  /// Hung by an illegal opcode, like the hardware. Nothing but a reset wakes it.
+  Locked,
+}
+
+/// T-cycles (dots) elapsed for every machine cycle
+pub const T_CYCLES_PER_M_CYCLE: u64 = 4;
+
 pub struct CPU {
   pub reg: Registers,
+  pub ime: Ime,
+  pub state: CpuState,
   pub log_enabled: bool,
+  /// Machine cycles executed since power on
+  pub cycles: u64,
   message_buffer: Vec<LogMessage>,
 }
 
@@ -17,7 +42,10 @@ impl CPU {
   pub fn new(log: bool) -> Self {
     CPU {
       reg: Registers::new(),
+      ime: Ime::new(),
+      state: CpuState::Running,
       log_enabled: log,
+      cycles: 0,
       message_buffer: vec!(),
     }
   }
@@ -26,16 +54,67 @@ impl CPU {
     self.message_buffer.push((event, message));
   }
 
-  pub fn tick(&mut self, bus: &mut VirtualMemory) {
+  /// Runs a single instruction, or services a pending interrupt, and returns
+  /// the machine cycles it consumed
+  pub fn tick(&mut self, bus: &mut VirtualMemory) -> u8 {
     self.message_buffer.clear();
     let pc = self.reg.pc.clone();
+
+    let wake = match self.state {
+      CpuState::Halted => bus.interrupts.pending().is_some(),
+      CpuState::Stopped => bus.interrupts.take_requests() & bus.interrupts.enable != 0,
+      CpuState::Locked => false,
+      _ => true,
+    };
+    if !wake {
+      self.cycles += 1;
+      return 1;
+    }
+    if self.state != CpuState::HaltBug {
+      self.state = CpuState::Running;
+    }
+
+    if self.ime.enabled {
+      if let Some(interrupt) = bus.interrupts.pending() {
+        self.log(LogEvents::Interrupt, format!("[INT] {:?} @ ADDR({:#06x})", interrupt, pc));
+        bus.interrupts.acknowledge(interrupt);
+        self.ime.disable();
+        let mut processor = self.decoder(bus);
+        processor.rst(interrupt.vector());
+        self.cycles += 5;
+        return 5;
+      }
+    }
+
     self.log(LogEvents::Tick, format!("[TICK] ADDR({:#06x})", pc));
-    let mut processor = OpcodeDecoder{
+    // EI enables interrupts only once the instruction following it is done
+    let enable_after = self.ime.is_scheduled();
+    let cycles = self.decoder(bus).run_opcode();
+    if self.state == CpuState::Stopped {
+      // Only requests made from now on wake it
+      bus.interrupts.take_requests();
+    }
+    if enable_after && self.ime.is_scheduled() {
+      self.ime.enable();
+    }
+    self.cycles += cycles as u64;
+    return cycles;
+  }
+
+  fn decoder<'tick>(&'tick mut self, bus: &'tick mut VirtualMemory) -> OpcodeDecoder<'tick> {
+    OpcodeDecoder{
       bus,
       reg: &mut self.reg,
-      message_buffer: &mut self.message_buffer
-    };
-    processor.run_opcode();
+      message_buffer: &mut self.message_buffer,
+      ime: &mut self.ime,
+      state: &mut self.state,
+      branch_taken: false,
+    }
+  }
+
+  /// T-cycles executed since power on
+  pub fn t_cycles(&self) -> u64 {
+    return self.cycles * T_CYCLES_PER_M_CYCLE;
   }
 }
 
@@ -45,4 +124,124 @@ impl LoggableComponent for CPU {
     self.message_buffer.clear();
     return messages;
   }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::interrupt::Interrupt;
+
+    #[test]
+    fn services_interrupt_one_instruction_after_ei() {
+      // EI; NOP; NOP
+      let mut bus = VirtualMemory::from_rom(&[0xFB, 0x00, 0x00]).unwrap();
+      let mut cpu = CPU::new(false);
+      cpu.reg.sp = 0xD000;
+      bus.interrupts.enable = Interrupt::Timer.mask();
+      bus.interrupts.request(Interrupt::Timer);
+
+      assert_eq!(cpu.tick(&mut bus), 1);
+      assert_eq!(cpu.tick(&mut bus), 1);
+      assert_eq!(cpu.reg.pc, 0x0002);
+
+      assert_eq!(cpu.tick(&mut bus), 5);
+      assert_eq!(cpu.reg.pc, 0x0050);
+      assert!(!cpu.ime.enabled);
+      assert_eq!(bus.interrupts.pending(), None);
+      assert_eq!(bus.fetch(0xCFFE), 0x02);
+      assert_eq!(cpu.cycles, 7);
+    }
+
+    #[test]
+    fn di_cancels_a_scheduled_ei() {
+      // EI; DI; NOP
+      let mut bus = VirtualMemory::from_rom(&[0xFB, 0xF3, 0x00]).unwrap();
+      let mut cpu = CPU::new(false);
+      bus.interrupts.enable = Interrupt::VBlank.mask();
+      bus.interrupts.request(Interrupt::VBlank);
+
+      cpu.tick(&mut bus);
+      cpu.tick(&mut bus);
+      cpu.tick(&mut bus);
+      assert!(!cpu.ime.enabled);
+      assert_eq!(cpu.reg.pc, 0x0003);
+    }
+
+    #[test]
+    fn halt_wakes_on_pending_interrupt_without_ime() {
+      // HALT; INC A
+      let mut bus = VirtualMemory::from_rom(&[0x76, 0x3C]).unwrap();
+      let mut cpu = CPU::new(false);
+      cpu.reg.a = 0;
+      bus.interrupts.enable = Interrupt::VBlank.mask();
+
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.state, CpuState::Halted);
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.reg.pc, 0x0001);
+
+      bus.interrupts.request(Interrupt::VBlank);
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.state, CpuState::Running);
+      assert_eq!(cpu.reg.a, 1);
+      assert_eq!(cpu.reg.pc, 0x0002);
+    }
+
+    #[test]
+    fn halt_bug_repeats_the_next_opcode_byte() {
+      // HALT; LD A,0x14 is decoded as LD A,0x3E; INC D
+      let mut bus = VirtualMemory::from_rom(&[0x76, 0x3E, 0x14]).unwrap();
+      let mut cpu = CPU::new(false);
+      cpu.reg.d = 0;
+      bus.interrupts.enable = Interrupt::Timer.mask();
+      bus.interrupts.request(Interrupt::Timer);
+
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.state, CpuState::HaltBug);
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.reg.a, 0x3E);
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.reg.d, 1);
+      assert_eq!(cpu.reg.pc, 0x0003);
+    }
+
+    #[test]
+    fn stop_resets_div_and_waits_for_a_new_interrupt() {
+      // STOP; NOP
+      let mut bus = VirtualMemory::from_rom(&[0x10, 0x00, 0x00]).unwrap();
+      let mut cpu = CPU::new(false);
+      bus.load_io_state(&[(0xFF04, 0xAB)]);
+      bus.interrupts.enable = Interrupt::Joypad.mask() | Interrupt::Timer.mask();
+      // Stale requests from before the STOP, never serviced with IME off
+      bus.interrupts.request(Interrupt::Timer);
+      bus.interrupts.request(Interrupt::Joypad);
+
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.state, CpuState::Stopped);
+      assert_eq!(bus.fetch(0xFF04), 0x00);
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.reg.pc, 0x0002);
+
+      // A second press wakes it even though the IF bit never went low
+      bus.interrupts.request(Interrupt::Joypad);
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.state, CpuState::Running);
+      assert_eq!(cpu.reg.pc, 0x0003);
+    }
+
+    #[test]
+    fn illegal_opcode_locks_the_cpu() {
+      // Illegal 0xD3; NOP
+      let mut bus = VirtualMemory::from_rom(&[0xD3, 0x00]).unwrap();
+      let mut cpu = CPU::new(false);
+      assert_eq!(cpu.tick(&mut bus), 1);
+      assert_eq!(cpu.state, CpuState::Locked);
+      assert!(cpu.dump_log_messages().iter().any(|(_, m)| m.contains("Illegal opcode 0xd3")));
+
+      cpu.ime.enable();
+      bus.interrupts.enable = 0x1F;
+      bus.interrupts.request(Interrupt::VBlank);
+      cpu.tick(&mut bus);
+      assert_eq!(cpu.state, CpuState::Locked);
+      assert_eq!(cpu.reg.pc, 0x0001);
+    }
+}
@@ -1,5 +1,7 @@
-use crate::{debug::logger::LogMessage, mmu::VirtualMemory, reg::RegCode, reg::{Flag, Registers}};
+use crate::{debug::logger::{LogEvents, LogMessage}, mmu::VirtualMemory, reg::RegCode, reg::{Flag, Registers}};
 use crate::cpu::opcode::*;
+use crate::cpu::interrupt::Ime;
+use crate::cpu::cpu::CpuState;
 
 pub enum BitMasks {
   Bit0 = 0b1,
@@ -12,10 +14,46 @@ pub enum BitMasks {
   Bit7 = 0b10000000,
 }
 
+// Machine cycles for every unprefixed opcode. Conditional branches hold the
+// not-taken cost, the extra cycles for a taken branch come from `branch_penalty`.
+// Illegal opcodes are zero, 0xCB is accounted for by `prefixed_opcode`.
+const OPCODE_CYCLES: [u8; 256] = [
+//0x0 0x1 0x2 0x3 0x4 0x5 0x6 0x7 0x8 0x9 0xA 0xB 0xC 0xD 0xE 0xF
+  1,  3,  2,  2,  1,  1,  2,  1,  5,  2,  2,  2,  1,  1,  2,  1, // 0x0_
+  1,  3,  2,  2,  1,  1,  2,  1,  3,  2,  2,  2,  1,  1,  2,  1, // 0x1_
+  2,  3,  2,  2,  1,  1,  2,  1,  2,  2,  2,  2,  1,  1,  2,  1, // 0x2_
+  2,  3,  2,  2,  3,  3,  3,  1,  2,  2,  2,  2,  1,  1,  2,  1, // 0x3_
+  1,  1,  1,  1,  1,  1,  2,  1,  1,  1,  1,  1,  1,  1,  2,  1, // 0x4_
+  1,  1,  1,  1,  1,  1,  2,  1,  1,  1,  1,  1,  1,  1,  2,  1, // 0x5_
+  1,  1,  1,  1,  1,  1,  2,  1,  1,  1,  1,  1,  1,  1,  2,  1, // 0x6_
+  2,  2,  2,  2,  2,  2,  1,  2,  1,  1,  1,  1,  1,  1,  2,  1, // 0x7_
+  1,  1,  1,  1,  1,  1,  2,  1,  1,  1,  1,  1,  1,  1,  2,  1, // 0x8_
+  1,  1,  1,  1,  1,  1,  2,  1,  1,  1,  1,  1,  1,  1,  2,  1, // 0x9_
+  1,  1,  1,  1,  1,  1,  2,  1,  1,  1,  1,  1,  1,  1,  2,  1, // 0xA_
+  1,  1,  1,  1,  1,  1,  2,  1,  1,  1,  1,  1,  1,  1,  2,  1, // 0xB_
+  2,  3,  3,  4,  3,  4,  2,  4,  2,  4,  3,  0,  3,  6,  2,  4, // 0xC_
+  2,  3,  3,  0,  3,  4,  2,  4,  2,  4,  3,  0,  3,  0,  2,  4, // 0xD_
+  3,  3,  2,  0,  0,  4,  2,  4,  4,  1,  4,  0,  0,  0,  2,  4, // 0xE_
+  3,  3,  2,  1,  0,  4,  2,  4,  3,  2,  4,  1,  0,  0,  2,  4, // 0xF_
+];
+
+fn branch_penalty(opcode: u8) -> u8 {
+  match opcode {
+    0x20 | 0x28 | 0x30 | 0x38 => 1,
+    0xC2 | 0xCA | 0xD2 | 0xDA => 1,
+    0xC4 | 0xCC | 0xD4 | 0xDC => 3,
+    0xC0 | 0xC8 | 0xD0 | 0xD8 => 3,
+    _ => 0,
+  }
+}
+
 pub struct OpcodeDecoder<'tick> {
   pub bus: &'tick mut VirtualMemory,
   pub reg: &'tick mut Registers,
-  pub message_buffer: &'tick mut Vec<LogMessage>
+  pub message_buffer: &'tick mut Vec<LogMessage>,
+  pub ime: &'tick mut Ime,
+  pub state: &'tick mut CpuState,
+  pub branch_taken: bool,
 }
 
 impl OpcodeDecoder<'_> {
@@ -34,14 +72,42 @@ impl OpcodeDecoder<'_> {
     return (((b.0 as u16) << 8) + (b.1 as u16)) as usize;
   }
 
+  /// Writes through the bus. Addresses wrap at 16 bits like the CPU's, so
+  /// every write lands on a mapped address.
+  pub fn write_byte(&mut self, addr: usize, byte: u8) {
+    self.bus.save(addr & 0xFFFF, byte).expect("every 16 bit address is mapped");
+  }
+
+  pub fn push_word(&mut self, word: u16) {
+    self.reg.sp = self.reg.sp.wrapping_sub(2);
+    self.write_byte(self.reg.sp as usize, (word & 0x00FF) as u8);
+    self.write_byte(self.reg.sp.wrapping_add(1) as usize, ((word & 0xFF00) >> 8) as u8);
+  }
+
+  pub fn pop_word(&mut self) -> u16 {
+    let right_byte = self.bus.fetch(self.reg.sp as usize);
+    let left_byte = self.bus.fetch(self.reg.sp.wrapping_add(1) as usize);
+    self.reg.sp = self.reg.sp.wrapping_add(2);
+    return Registers::_8bit_to_16bit(left_byte, right_byte);
+  }
+
   pub fn fetch_post_increment(&mut self) -> u8 {
     let op_code = self.bus.fetch(self.reg.pc);
     self.reg.inc_pc();
     return op_code;
   }
 
-  pub fn run_opcode(&mut self) {
-    let opcode = self.fetch_post_increment();
+  /// Executes the instruction at PC and returns the machine cycles it took
+  pub fn run_opcode(&mut self) -> u8 {
+    let opcode = match self.state {
+      CpuState::HaltBug => {
+        *self.state = CpuState::Running;
+        self.bus.fetch(self.reg.pc)
+      }
+      _ => self.fetch_post_increment(),
+    };
+    let mut cycles = OPCODE_CYCLES[opcode as usize];
+    self.branch_taken = false;
     match opcode {
       0x00 => (),
       0x01 | 0x11 | 0x21 | 0x31 => {
@@ -91,8 +157,32 @@ impl OpcodeDecoder<'_> {
         }
       }
 
-      0x07 => self.rlc(RegCode::A),
-      0x17 => self.rl(RegCode::A),
+      0x08 => {
+        let addr = self.read_next_addr();
+        self.ld(addr, self.reg.sp);
+      }
+
+      0x09 => self.add_hl(RegCode::BC),
+      0x19 => self.add_hl(RegCode::DE),
+      0x29 => self.add_hl(RegCode::HL),
+      0x39 => self.add_hl(RegCode::SP),
+
+      // RLCA/RLA/RRCA/RRA behave like their CB counterparts but always clear Z
+      0x07 => { self.rlc(RegCode::A); self.reg.set_flag(&Flag::Zero, false); }
+      0x17 => { self.rl(RegCode::A); self.reg.set_flag(&Flag::Zero, false); }
+      0x0F => { self.rrc(RegCode::A); self.reg.set_flag(&Flag::Zero, false); }
+      0x1F => { self.rr(RegCode::A); self.reg.set_flag(&Flag::Zero, false); }
+
+      0x27 => self.daa(),
+      0x2F => self.cpl(),
+      0x37 => self.scf(),
+      0x3F => self.ccf(),
+
+      0x10 => {
+        self.read_next_8bit();
+        self.stop();
+      }
+      0x76 => self.halt(),
 
       0x0a => self.ld(RegCode::A, self.reg.get_16bit(&RegCode::BC) as usize),
       0x1a => self.ld(RegCode::A, self.reg.get_16bit(&RegCode::DE) as usize),
@@ -104,6 +194,11 @@ impl OpcodeDecoder<'_> {
       0x2c => self.inc(RegCode::L),
       0x3c => self.inc(RegCode::A),
 
+      0x0d => self.dec(RegCode::C),
+      0x1d => self.dec(RegCode::E),
+      0x2d => self.dec(RegCode::L),
+      0x3d => self.dec(RegCode::A),
+
       0x0e | 0x1e | 0x2e | 0x3e => {
         let byte = self.read_next_8bit();
         match opcode {
@@ -183,6 +278,51 @@ impl OpcodeDecoder<'_> {
       0x7E => self.ld(RegCode::A, self.reg.get_16bit(&RegCode::HL) as usize),
       0x7F => self.ld(RegCode::A, self.reg.a),
 
+      0x80 => self.add(RegCode::B),
+      0x81 => self.add(RegCode::C),
+      0x82 => self.add(RegCode::D),
+      0x83 => self.add(RegCode::E),
+      0x84 => self.add(RegCode::H),
+      0x85 => self.add(RegCode::L),
+      0x86 => self.add(self.reg.get_16bit(&RegCode::HL) as usize),
+      0x87 => self.add(RegCode::A),
+
+      0x88 => self.adc(RegCode::B),
+      0x89 => self.adc(RegCode::C),
+      0x8A => self.adc(RegCode::D),
+      0x8B => self.adc(RegCode::E),
+      0x8C => self.adc(RegCode::H),
+      0x8D => self.adc(RegCode::L),
+      0x8E => self.adc(self.reg.get_16bit(&RegCode::HL) as usize),
+      0x8F => self.adc(RegCode::A),
+
+      0x90 => self.sub(RegCode::B),
+      0x91 => self.sub(RegCode::C),
+      0x92 => self.sub(RegCode::D),
+      0x93 => self.sub(RegCode::E),
+      0x94 => self.sub(RegCode::H),
+      0x95 => self.sub(RegCode::L),
+      0x96 => self.sub(self.reg.get_16bit(&RegCode::HL) as usize),
+      0x97 => self.sub(RegCode::A),
+
+      0x98 => self.sbc(RegCode::B),
+      0x99 => self.sbc(RegCode::C),
+      0x9A => self.sbc(RegCode::D),
+      0x9B => self.sbc(RegCode::E),
+      0x9C => self.sbc(RegCode::H),
+      0x9D => self.sbc(RegCode::L),
+      0x9E => self.sbc(self.reg.get_16bit(&RegCode::HL) as usize),
+      0x9F => self.sbc(RegCode::A),
+
+      0xA0 => self.and(RegCode::B),
+      0xA1 => self.and(RegCode::C),
+      0xA2 => self.and(RegCode::D),
+      0xA3 => self.and(RegCode::E),
+      0xA4 => self.and(RegCode::H),
+      0xA5 => self.and(RegCode::L),
+      0xA6 => self.and(self.reg.get_16bit(&RegCode::HL) as usize),
+      0xA7 => self.and(RegCode::A),
+
       0xA8 => self.xor(RegCode::B),
       0xA9 => self.xor(RegCode::C),
       0xAA => self.xor(RegCode::D),
@@ -192,14 +332,32 @@ impl OpcodeDecoder<'_> {
       0xAE => self.xor(self.reg.get_16bit(&RegCode::HL) as usize),
       0xAF => self.xor(RegCode::A),
 
-      0x20 | 0x30 | 0x81 | 0x82 | 0x83 => {
+      0xB0 => self.or(RegCode::B),
+      0xB1 => self.or(RegCode::C),
+      0xB2 => self.or(RegCode::D),
+      0xB3 => self.or(RegCode::E),
+      0xB4 => self.or(RegCode::H),
+      0xB5 => self.or(RegCode::L),
+      0xB6 => self.or(self.reg.get_16bit(&RegCode::HL) as usize),
+      0xB7 => self.or(RegCode::A),
+
+      0xB8 => self.cp(RegCode::B),
+      0xB9 => self.cp(RegCode::C),
+      0xBA => self.cp(RegCode::D),
+      0xBB => self.cp(RegCode::E),
+      0xBC => self.cp(RegCode::H),
+      0xBD => self.cp(RegCode::L),
+      0xBE => self.cp(self.reg.get_16bit(&RegCode::HL) as usize),
+      0xBF => self.cp(RegCode::A),
+
+      0x18 | 0x20 | 0x28 | 0x30 | 0x38 => {
         let byte = self.read_next_8bit();
         match opcode {
+          0x18 => self.jr(true, byte),
           0x20 => self.jr(!self.reg.check_flag(&Flag::Zero), byte),
+          0x28 => self.jr(self.reg.check_flag(&Flag::Zero), byte),
           0x30 => self.jr(!self.reg.check_flag(&Flag::CarryFlag), byte),
-          0x81 => self.jr(true, byte),
-          0x82 => self.jr(self.reg.check_flag(&Flag::Zero), byte),
-          0x83 => self.jr(self.reg.check_flag(&Flag::CarryFlag), byte),
+          0x38 => self.jr(self.reg.check_flag(&Flag::CarryFlag), byte),
           _ => ()
         }
       }
@@ -215,6 +373,7 @@ impl OpcodeDecoder<'_> {
           _ => (),
         }
       }
+      0xE9 => self.jp(true, self.reg.get_16bit(&RegCode::HL) as usize),
 
       0xE0 | 0xF0 => {
         let byte = self.read_next_8bit();
@@ -225,7 +384,17 @@ impl OpcodeDecoder<'_> {
         }
       }
       0xE2 => self.ldh(self.reg.get_8bit(&RegCode::C) as usize, RegCode::A),
-      0xEF => self.ldh(RegCode::A, self.reg.get_8bit(&RegCode::C) as usize),
+      0xF2 => self.ldh(RegCode::A, self.reg.get_8bit(&RegCode::C) as usize),
+
+      0xE8 | 0xF8 => {
+        let byte = self.read_next_8bit();
+        match opcode {
+          0xE8 => self.add_sp(byte),
+          0xF8 => self.ld_hl_sp(byte),
+          _ => (),
+        }
+      }
+      0xF9 => self.ld(RegCode::SP, (self.reg.h, self.reg.l)),
 
       0xEA | 0xFA => {
         let addr = self.read_next_addr();
@@ -254,29 +423,224 @@ impl OpcodeDecoder<'_> {
       0xF5 => self.push(RegCode::AF),
 
       0xC1 => self.pop(RegCode::BC),
-      0xD1 => self.pop(RegCode::BC),
-      0xE1 => self.pop(RegCode::BC),
-      0xF1 => self.pop(RegCode::BC),
-
-      0xC9 => self.ret(),
-
-      0xCB => self.prefixed_opcode(),
-
-      0xFE => {
+      0xD1 => self.pop(RegCode::DE),
+      0xE1 => self.pop(RegCode::HL),
+      0xF1 => self.pop(RegCode::AF),
+
+      0xC9 => self.ret(true),
+      0xC0 => self.ret(!self.reg.check_flag(&Flag::Zero)),
+      0xC8 => self.ret(self.reg.check_flag(&Flag::Zero)),
+      0xD0 => self.ret(!self.reg.check_flag(&Flag::CarryFlag)),
+      0xD8 => self.ret(self.reg.check_flag(&Flag::CarryFlag)),
+      0xD9 => self.reti(),
+
+      0xF3 => self.di(),
+      0xFB => self.ei(),
+
+      0xC7 => self.rst(0x00),
+      0xCF => self.rst(0x08),
+      0xD7 => self.rst(0x10),
+      0xDF => self.rst(0x18),
+      0xE7 => self.rst(0x20),
+      0xEF => self.rst(0x28),
+      0xF7 => self.rst(0x30),
+      0xFF => self.rst(0x38),
+
+      0xCB => cycles = self.prefixed_opcode(),
+
+      0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => {
         let next = self.read_next_8bit();
-        self.cp(next);
+        match opcode {
+          0xC6 => self.add(next),
+          0xCE => self.adc(next),
+          0xD6 => self.sub(next),
+          0xDE => self.sbc(next),
+          0xE6 => self.and(next),
+          0xEE => self.xor(next),
+          0xF6 => self.or(next),
+          0xFE => self.cp(next),
+          _ => (),
+        }
       }
 
-      _ => panic!("Unimplemented code: {:#04x}", opcode)
+      0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+        let addr = self.reg.pc.wrapping_sub(1);
+        self.message_buffer.push((LogEvents::Decoding, format!("[LOCKUP] Illegal opcode {:#04x} @ ADDR({:#06x})", opcode, addr)));
+        *self.state = CpuState::Locked;
+        cycles = 1;
+      }
     };
+
+    if self.branch_taken {
+      cycles += branch_penalty(opcode);
+    }
+    return cycles;
   }
 
-  pub fn prefixed_opcode(&mut self) {
+  pub fn prefixed_opcode(&mut self) -> u8 {
     let next_byte = self.fetch_post_increment();
-    match next_byte {
-      0x11 => self.rl(RegCode::C),
-      0x7C => self.check_bit(RegCode::H, BitMasks::Bit7 as u8),
-      _ => (),
+    match next_byte & 0b111 {
+      0x0 => self.run_prefixed(next_byte, RegCode::B),
+      0x1 => self.run_prefixed(next_byte, RegCode::C),
+      0x2 => self.run_prefixed(next_byte, RegCode::D),
+      0x3 => self.run_prefixed(next_byte, RegCode::E),
+      0x4 => self.run_prefixed(next_byte, RegCode::H),
+      0x5 => self.run_prefixed(next_byte, RegCode::L),
+      0x6 => self.run_prefixed(next_byte, self.reg.get_16bit(&RegCode::HL) as usize),
+      _ => self.run_prefixed(next_byte, RegCode::A),
+    }
+
+    return match (next_byte & 0b111, next_byte) {
+      (0x6, 0x40..=0x7F) => 3,
+      (0x6, _) => 4,
+      _ => 2,
+    };
+  }
+
+  // Bits 0-2 of a CB opcode select the operand, bits 3-5 the bit index
+  // (or the rotate/shift kind for 0x00-0x3F) and bits 6-7 the operation
+  fn run_prefixed<S>(&mut self, opcode: u8, target: S) where Self: Rotate<S> + BitCheck<S> {
+    let mask = 1 << ((opcode >> 3) & 0b111);
+    match opcode {
+      0x00..=0x07 => self.rlc(target),
+      0x08..=0x0F => self.rrc(target),
+      0x10..=0x17 => self.rl(target),
+      0x18..=0x1F => self.rr(target),
+      0x20..=0x27 => self.sla(target),
+      0x28..=0x2F => self.sra(target),
+      0x30..=0x37 => self.swap(target),
+      0x38..=0x3F => self.srl(target),
+      0x40..=0x7F => self.check_bit(target, mask),
+      0x80..=0xBF => self.reset_bit(target, mask),
+      0xC0..=0xFF => self.set_bit(target, mask),
     }
   }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(program: &[u8], reg: &mut Registers) -> VirtualMemory {
+      let mut bus = VirtualMemory::from_rom(program).unwrap();
+      let mut message_buffer = vec!();
+      let mut ime = Ime::new();
+      let mut state = CpuState::Running;
+      reg.pc = 0;
+      while reg.pc < program.len() {
+        let mut decoder = OpcodeDecoder { bus: &mut bus, reg, message_buffer: &mut message_buffer, ime: &mut ime, state: &mut state, branch_taken: false };
+        decoder.run_opcode();
+      }
+      bus
+    }
+
+    #[test]
+    fn add_sets_half_carry_and_carry() {
+      let mut reg = Registers::new();
+      // LD A,0xF8; ADD A,0x08
+      run(&[0x3E, 0xF8, 0xC6, 0x08], &mut reg);
+      assert_eq!(reg.a, 0x00);
+      assert!(reg.check_flag(&Flag::Zero));
+      assert!(reg.check_flag(&Flag::HalfCarryFlagBCD));
+      assert!(reg.check_flag(&Flag::CarryFlag));
+      assert!(!reg.check_flag(&Flag::AddSubBCD));
+    }
+
+    #[test]
+    fn sbc_borrows_carry() {
+      let mut reg = Registers::new();
+      // SCF; LD A,0x10; LD B,0x0F; SBC A,B
+      run(&[0x37, 0x3E, 0x10, 0x06, 0x0F, 0x98], &mut reg);
+      assert_eq!(reg.a, 0x00);
+      assert!(reg.check_flag(&Flag::Zero));
+      assert!(reg.check_flag(&Flag::AddSubBCD));
+      assert!(reg.check_flag(&Flag::HalfCarryFlagBCD));
+      assert!(!reg.check_flag(&Flag::CarryFlag));
+    }
+
+    #[test]
+    fn daa_adjusts_bcd_addition() {
+      let mut reg = Registers::new();
+      // LD A,0x45; ADD A,0x38; DAA
+      run(&[0x3E, 0x45, 0xC6, 0x38, 0x27], &mut reg);
+      assert_eq!(reg.a, 0x83);
+      assert!(!reg.check_flag(&Flag::CarryFlag));
+    }
+
+    #[test]
+    fn push_pop_af_masks_low_nibble_of_f() {
+      let mut reg = Registers::new();
+      // LD SP,0xD000; LD BC,0x12FF; PUSH BC; POP AF
+      run(&[0x31, 0x00, 0xD0, 0x01, 0xFF, 0x12, 0xC5, 0xF1], &mut reg);
+      assert_eq!(reg.a, 0x12);
+      assert_eq!(reg.f, 0xF0);
+      assert_eq!(reg.sp, 0xD000);
+    }
+
+    #[test]
+    fn call_pushes_return_address_little_endian() {
+      let mut reg = Registers::new();
+      // LD SP,0xD000; CALL 0x0008
+      let mut bus = run(&[0x31, 0x00, 0xD0, 0xCD, 0x08, 0x00, 0x00, 0x00], &mut reg);
+      assert_eq!(reg.pc, 0x0008);
+      assert_eq!(bus.fetch(0xCFFE), 0x06);
+      assert_eq!(bus.fetch(0xCFFF), 0x00);
+    }
+
+    #[test]
+    fn add_sp_signed_offset_sets_low_byte_carries() {
+      let mut reg = Registers::new();
+      // LD SP,0x00FF; LD HL,SP-1
+      run(&[0x31, 0xFF, 0x00, 0xF8, 0xFF], &mut reg);
+      assert_eq!(reg.get_16bit(&RegCode::HL), 0x00FE);
+      assert!(reg.check_flag(&Flag::HalfCarryFlagBCD));
+      assert!(reg.check_flag(&Flag::CarryFlag));
+      assert!(!reg.check_flag(&Flag::Zero));
+    }
+
+    #[test]
+    fn cb_swap_and_srl_on_registers() {
+      let mut reg = Registers::new();
+      // LD B,0xF1; SWAP B; LD C,0x01; SRL C
+      run(&[0x06, 0xF1, 0xCB, 0x30, 0x0E, 0x01, 0xCB, 0x39], &mut reg);
+      assert_eq!(reg.b, 0x1F);
+      assert_eq!(reg.c, 0x00);
+      assert!(reg.check_flag(&Flag::Zero));
+      assert!(reg.check_flag(&Flag::CarryFlag));
+    }
+
+    #[test]
+    fn cb_read_modify_write_through_hl() {
+      let mut reg = Registers::new();
+      // LD HL,0xC000; LD (HL),0x81; RLC (HL); SET 6,(HL); RES 0,(HL); BIT 7,(HL)
+      let mut bus = run(&[0x21, 0x00, 0xC0, 0x36, 0x81, 0xCB, 0x06, 0xCB, 0xF6, 0xCB, 0x86, 0xCB, 0x7E], &mut reg);
+      assert_eq!(bus.fetch(0xC000), 0x42);
+      assert!(reg.check_flag(&Flag::Zero));
+      assert!(reg.check_flag(&Flag::HalfCarryFlagBCD));
+      assert!(reg.check_flag(&Flag::CarryFlag));
+    }
+
+    #[test]
+    fn reports_taken_and_not_taken_branch_cycles() {
+      // XOR A; JR NZ,+0; JR Z,+0; CALL NZ,0x0000; BIT 0,(HL)
+      let mut bus = VirtualMemory::from_rom(&[0xAF, 0x20, 0x00, 0x28, 0x00, 0xC4, 0x00, 0x00, 0xCB, 0x46]).unwrap();
+      let mut reg = Registers::new();
+      reg.pc = 0;
+      let mut message_buffer = vec!();
+      let mut ime = Ime::new();
+      let mut state = CpuState::Running;
+      let mut cycles = vec!();
+      for _ in 0..5 {
+        let mut decoder = OpcodeDecoder { bus: &mut bus, reg: &mut reg, message_buffer: &mut message_buffer, ime: &mut ime, state: &mut state, branch_taken: false };
+        cycles.push(decoder.run_opcode());
+      }
+      assert_eq!(cycles, vec!(1, 2, 3, 3, 3));
+    }
+
+    #[test]
+    fn word_stores_wrap_at_the_top_of_memory() {
+      let mut reg = Registers::new();
+      // LD SP,0x1234; LD (0xFFFF),SP
+      let bus = run(&[0x31, 0x34, 0x12, 0x08, 0xFF, 0xFF], &mut reg);
+      assert_eq!(bus.interrupts.enable, 0x34);
+    }
+}
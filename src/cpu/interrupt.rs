@@ -0,0 +1,132 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+  VBlank,
+  LcdStat,
+  Timer,
+  Serial,
+  Joypad,
+}
+
+// Ordered by priority, VBlank is serviced first
+const INTERRUPTS: [Interrupt; 5] = [
+  Interrupt::VBlank,
+  Interrupt::LcdStat,
+  Interrupt::Timer,
+  Interrupt::Serial,
+  Interrupt::Joypad,
+];
+
+impl Interrupt {
+  pub fn mask(&self) -> u8 {
+    return match self {
+      Interrupt::VBlank => 0b00001,
+      Interrupt::LcdStat => 0b00010,
+      Interrupt::Timer => 0b00100,
+      Interrupt::Serial => 0b01000,
+      Interrupt::Joypad => 0b10000,
+    }
+  }
+
+  pub fn vector(&self) -> usize {
+    return match self {
+      Interrupt::VBlank => 0x40,
+      Interrupt::LcdStat => 0x48,
+      Interrupt::Timer => 0x50,
+      Interrupt::Serial => 0x58,
+      Interrupt::Joypad => 0x60,
+    }
+  }
+}
+
+/// IE (0xFFFF) and IF (0xFF0F) registers
+pub struct InterruptController {
+  pub enable: u8,
+  pub flag: u8,
+  /// Sources requested since the last `take_requests`, even when their IF bit
+  /// was already set
+  requested: u8,
+}
+
+impl InterruptController {
+  pub fn new() -> Self {
+    InterruptController { enable: 0x00, flag: 0x00, requested: 0x00 }
+  }
+
+  pub fn request(&mut self, interrupt: Interrupt) {
+    self.flag |= interrupt.mask();
+    self.requested |= interrupt.mask();
+  }
+
+  /// New requests since the last call, then forgets them
+  pub fn take_requests(&mut self) -> u8 {
+    return std::mem::take(&mut self.requested);
+  }
+
+  pub fn acknowledge(&mut self, interrupt: Interrupt) {
+    self.flag &= !interrupt.mask();
+  }
+
+  /// Highest priority interrupt that is both requested and enabled
+  pub fn pending(&self) -> Option<Interrupt> {
+    let active = self.enable & self.flag;
+    return INTERRUPTS.iter().find(|i| active & i.mask() != 0).copied();
+  }
+
+  pub fn read_flag(&self) -> u8 {
+    // Only the lower 5 bits are wired, the rest read back as 1
+    return self.flag | 0xE0;
+  }
+
+  pub fn write_flag(&mut self, byte: u8) {
+    self.flag = byte & 0x1F;
+  }
+}
+
+/// Interrupt master enable. EI only takes effect after the following instruction.
+pub struct Ime {
+  pub enabled: bool,
+  scheduled: bool,
+}
+
+impl Ime {
+  pub fn new() -> Self {
+    Ime { enabled: false, scheduled: false }
+  }
+
+  pub fn schedule_enable(&mut self) {
+    self.scheduled = true;
+  }
+
+  pub fn enable(&mut self) {
+    self.enabled = true;
+    self.scheduled = false;
+  }
+
+  pub fn disable(&mut self) {
+    self.enabled = false;
+    self.scheduled = false;
+  }
+
+  pub fn is_scheduled(&self) -> bool {
+    return self.scheduled;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_respects_enable_mask_and_priority() {
+      let mut ic = InterruptController::new();
+      ic.request(Interrupt::Joypad);
+      ic.request(Interrupt::Timer);
+      assert_eq!(ic.pending(), None);
+
+      ic.enable = 0x1F;
+      assert_eq!(ic.pending(), Some(Interrupt::Timer));
+      ic.acknowledge(Interrupt::Timer);
+      assert_eq!(ic.pending(), Some(Interrupt::Joypad));
+      assert_eq!(ic.read_flag(), 0xF0);
+    }
+}
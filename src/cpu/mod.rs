@@ -1,3 +1,4 @@
 pub mod cpu;
 pub mod opcode;
 pub mod decoder;
+pub mod interrupt;
@@ -1,5 +1,6 @@
 use crate::{reg::RegCode, reg::{Flag}};
 use crate::cpu::decoder::{OpcodeDecoder,BitMasks};
+use crate::cpu::cpu::CpuState;
 use crate::debug::logger::LogEvents;
 
 pub trait Load<D,S> {
@@ -23,6 +24,30 @@ pub trait IncDec<S> {
   fn dec(&mut self, source: S);
 }
 
+pub trait Add<S> {
+  fn add(&mut self, source: S);
+}
+
+pub trait AddCarry<S> {
+  fn adc(&mut self, source: S);
+}
+
+pub trait Sub<S> {
+  fn sub(&mut self, source: S);
+}
+
+pub trait SubCarry<S> {
+  fn sbc(&mut self, source: S);
+}
+
+pub trait And<S> {
+  fn and(&mut self, source: S);
+}
+
+pub trait Or<S> {
+  fn or(&mut self, source: S);
+}
+
 pub trait Xor<S> {
   fn xor(&mut self, source: S);
 }
@@ -31,9 +56,33 @@ pub trait Compare<S> {
   fn cp(&mut self, source: S);
 }
 
-pub trait Rotate {
-  fn rl(&mut self, r: RegCode);
-  fn rlc(&mut self, r: RegCode);
+pub trait AddWide {
+  fn add_hl(&mut self, source: RegCode);
+  fn add_sp(&mut self, offset: u8);
+  fn ld_hl_sp(&mut self, offset: u8);
+}
+
+pub trait Accumulator {
+  fn daa(&mut self);
+  fn cpl(&mut self);
+  fn scf(&mut self);
+  fn ccf(&mut self);
+}
+
+pub trait Operand<S> {
+  fn read_operand(&mut self, source: &S) -> u8;
+  fn write_operand(&mut self, dest: &S, byte: u8);
+}
+
+pub trait Rotate<S> {
+  fn rl(&mut self, target: S);
+  fn rlc(&mut self, target: S);
+  fn rr(&mut self, target: S);
+  fn rrc(&mut self, target: S);
+  fn sla(&mut self, target: S);
+  fn sra(&mut self, target: S);
+  fn srl(&mut self, target: S);
+  fn swap(&mut self, target: S);
 }
 
 pub trait Jump {
@@ -43,13 +92,27 @@ pub trait Jump {
 
 pub trait Stack {
   fn call(&mut self, condition: bool, next: usize);
-  fn ret(&mut self);
+  fn ret(&mut self, condition: bool);
+  fn rst(&mut self, vector: usize);
   fn push(&mut self, reg: RegCode);
   fn pop(&mut self, reg: RegCode);
 }
 
-pub trait BitCheck {
-  fn check_bit(&mut self, reg: RegCode, mask: u8);
+pub trait InterruptControl {
+  fn di(&mut self);
+  fn ei(&mut self);
+  fn reti(&mut self);
+}
+
+pub trait LowPower {
+  fn halt(&mut self);
+  fn stop(&mut self);
+}
+
+pub trait BitCheck<S> {
+  fn check_bit(&mut self, target: S, mask: u8);
+  fn reset_bit(&mut self, target: S, mask: u8);
+  fn set_bit(&mut self, target: S, mask: u8);
 }
 
  /********************************************************************
@@ -82,13 +145,20 @@ impl Load<RegCode, &(u8,u8)> for OpcodeDecoder<'_> {
 
 impl Load<usize, RegCode> for OpcodeDecoder<'_> {
   fn ld(&mut self, addr: usize, source: RegCode) {
-    self.bus.save(addr, self.reg.get_8bit(&source));
+    self.write_byte(addr, self.reg.get_8bit(&source));
   }
 }
 
 impl Load<usize, u8> for OpcodeDecoder<'_> {
   fn ld(&mut self, addr: usize, byte: u8) {
-    self.bus.save(addr, byte);
+    self.write_byte(addr, byte);
+  }
+}
+
+impl Load<usize, u16> for OpcodeDecoder<'_> {
+  fn ld(&mut self, addr: usize, word: u16) {
+    self.write_byte(addr, (word & 0x00FF) as u8);
+    self.write_byte(addr + 1, ((word & 0xFF00) >> 8) as u8);
   }
 }
 
@@ -136,46 +206,286 @@ impl LoadHigh<RegCode,usize> for OpcodeDecoder<'_> {
 impl LoadHigh<usize,RegCode> for OpcodeDecoder<'_> {
   fn ldh(&mut self, delta: usize, code: RegCode) {
     let byte = self.reg.get_8bit(&code);
-    match self.bus.save(0xFF00 + delta as usize, byte) {
-      Ok(_) => (),
-      Err(_) => panic!("failed")
-    }
+    self.write_byte(0xFF00 + delta, byte);
   }
 }
 
-impl Xor<RegCode> for OpcodeDecoder<'_> {
-  fn xor(&mut self, code: RegCode) {
-    let mut v = self.reg.get_8bit(&code);
-    v = self.reg.a ^ v;
+impl Add<u8> for OpcodeDecoder<'_> {
+  fn add(&mut self, byte: u8) {
+    let a = self.reg.a;
+    let result = a.wrapping_add(byte);
+    self.reg.set_flag(&Flag::Zero, result == 0);
+    self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, (a & 0xF) + (byte & 0xF) > 0xF);
+    self.reg.set_flag(&Flag::CarryFlag, (a as u16) + (byte as u16) > 0xFF);
+    self.reg.set_8bit(&RegCode::A, result);
+  }
+}
+
+impl Add<RegCode> for OpcodeDecoder<'_> {
+  fn add(&mut self, code: RegCode) {
+    let byte = self.reg.get_8bit(&code);
+    self.add(byte);
+  }
+}
+
+impl Add<usize> for OpcodeDecoder<'_> {
+  fn add(&mut self, addr: usize) {
+    let byte = self.bus.fetch(addr);
+    self.add(byte);
+  }
+}
+
+impl AddCarry<u8> for OpcodeDecoder<'_> {
+  fn adc(&mut self, byte: u8) {
+    let a = self.reg.a;
+    let carry = self.reg.check_flag(&Flag::CarryFlag) as u8;
+    let result = a.wrapping_add(byte).wrapping_add(carry);
+    self.reg.set_flag(&Flag::Zero, result == 0);
+    self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, (a & 0xF) + (byte & 0xF) + carry > 0xF);
+    self.reg.set_flag(&Flag::CarryFlag, (a as u16) + (byte as u16) + (carry as u16) > 0xFF);
+    self.reg.set_8bit(&RegCode::A, result);
+  }
+}
+
+impl AddCarry<RegCode> for OpcodeDecoder<'_> {
+  fn adc(&mut self, code: RegCode) {
+    let byte = self.reg.get_8bit(&code);
+    self.adc(byte);
+  }
+}
+
+impl AddCarry<usize> for OpcodeDecoder<'_> {
+  fn adc(&mut self, addr: usize) {
+    let byte = self.bus.fetch(addr);
+    self.adc(byte);
+  }
+}
+
+impl Sub<u8> for OpcodeDecoder<'_> {
+  fn sub(&mut self, byte: u8) {
+    self.cp(byte);
+    self.reg.set_8bit(&RegCode::A, self.reg.a.wrapping_sub(byte));
+  }
+}
+
+impl Sub<RegCode> for OpcodeDecoder<'_> {
+  fn sub(&mut self, code: RegCode) {
+    let byte = self.reg.get_8bit(&code);
+    self.sub(byte);
+  }
+}
+
+impl Sub<usize> for OpcodeDecoder<'_> {
+  fn sub(&mut self, addr: usize) {
+    let byte = self.bus.fetch(addr);
+    self.sub(byte);
+  }
+}
+
+impl SubCarry<u8> for OpcodeDecoder<'_> {
+  fn sbc(&mut self, byte: u8) {
+    let a = self.reg.a;
+    let carry = self.reg.check_flag(&Flag::CarryFlag) as u8;
+    let result = a.wrapping_sub(byte).wrapping_sub(carry);
+    self.reg.set_flag(&Flag::Zero, result == 0);
+    self.reg.set_flag(&Flag::AddSubBCD, true);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, (a & 0xF) < (byte & 0xF) + carry);
+    self.reg.set_flag(&Flag::CarryFlag, (a as u16) < (byte as u16) + (carry as u16));
+    self.reg.set_8bit(&RegCode::A, result);
+  }
+}
+
+impl SubCarry<RegCode> for OpcodeDecoder<'_> {
+  fn sbc(&mut self, code: RegCode) {
+    let byte = self.reg.get_8bit(&code);
+    self.sbc(byte);
+  }
+}
+
+impl SubCarry<usize> for OpcodeDecoder<'_> {
+  fn sbc(&mut self, addr: usize) {
+    let byte = self.bus.fetch(addr);
+    self.sbc(byte);
+  }
+}
+
+impl And<u8> for OpcodeDecoder<'_> {
+  fn and(&mut self, byte: u8) {
+    let v = self.reg.a & byte;
     self.reg.set_flag(&Flag::Zero, v == 0);
-    self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
     self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, true);
+    self.reg.set_flag(&Flag::CarryFlag, false);
     self.reg.set_8bit(&RegCode::A, v);
   }
 }
 
-impl Xor<usize> for OpcodeDecoder<'_> {
-  fn xor(&mut self, addr: usize) {
-    let mut v = self.bus.fetch(addr);
-    v = self.reg.a ^ v;
+impl And<RegCode> for OpcodeDecoder<'_> {
+  fn and(&mut self, code: RegCode) {
+    let byte = self.reg.get_8bit(&code);
+    self.and(byte);
+  }
+}
+
+impl And<usize> for OpcodeDecoder<'_> {
+  fn and(&mut self, addr: usize) {
+    let byte = self.bus.fetch(addr);
+    self.and(byte);
+  }
+}
+
+impl Or<u8> for OpcodeDecoder<'_> {
+  fn or(&mut self, byte: u8) {
+    let v = self.reg.a | byte;
+    self.reg.set_flag(&Flag::Zero, v == 0);
+    self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
+    self.reg.set_flag(&Flag::CarryFlag, false);
+    self.reg.set_8bit(&RegCode::A, v);
+  }
+}
+
+impl Or<RegCode> for OpcodeDecoder<'_> {
+  fn or(&mut self, code: RegCode) {
+    let byte = self.reg.get_8bit(&code);
+    self.or(byte);
+  }
+}
+
+impl Or<usize> for OpcodeDecoder<'_> {
+  fn or(&mut self, addr: usize) {
+    let byte = self.bus.fetch(addr);
+    self.or(byte);
+  }
+}
+
+impl Xor<u8> for OpcodeDecoder<'_> {
+  fn xor(&mut self, byte: u8) {
+    let v = self.reg.a ^ byte;
     self.reg.set_flag(&Flag::Zero, v == 0);
     self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
     self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::CarryFlag, false);
     self.reg.set_8bit(&RegCode::A, v);
   }
 }
 
+impl Xor<RegCode> for OpcodeDecoder<'_> {
+  fn xor(&mut self, code: RegCode) {
+    let byte = self.reg.get_8bit(&code);
+    self.xor(byte);
+  }
+}
+
+impl Xor<usize> for OpcodeDecoder<'_> {
+  fn xor(&mut self, addr: usize) {
+    let byte = self.bus.fetch(addr);
+    self.xor(byte);
+  }
+}
+
 impl Compare<u8> for OpcodeDecoder<'_> {
   fn cp(&mut self, data: u8) {
     let result = self.reg.a.wrapping_sub(data);
     self.reg.set_flag(&Flag::Zero, result == 0);
-    // @TODO: Implement half carry correctly
     self.reg.set_flag(&Flag::AddSubBCD, true);
-    self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, (self.reg.a & 0xF) < (data & 0xF));
     self.reg.set_flag(&Flag::CarryFlag, data > self.reg.a);
   }
 }
 
+impl Compare<RegCode> for OpcodeDecoder<'_> {
+  fn cp(&mut self, code: RegCode) {
+    let byte = self.reg.get_8bit(&code);
+    self.cp(byte);
+  }
+}
+
+impl Compare<usize> for OpcodeDecoder<'_> {
+  fn cp(&mut self, addr: usize) {
+    let byte = self.bus.fetch(addr);
+    self.cp(byte);
+  }
+}
+
+impl AddWide for OpcodeDecoder<'_> {
+  fn add_hl(&mut self, code: RegCode) {
+    let hl = self.reg.get_16bit(&RegCode::HL);
+    let source = self.reg.get_16bit(&code);
+    let result = hl.wrapping_add(source);
+    self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, (hl & 0xFFF) + (source & 0xFFF) > 0xFFF);
+    self.reg.set_flag(&Flag::CarryFlag, (hl as u32) + (source as u32) > 0xFFFF);
+    self.reg.set_16bit(&RegCode::HL, (result >> 8) as u8, (result & 0x00FF) as u8);
+  }
+
+  fn add_sp(&mut self, offset: u8) {
+    self.reg.sp = self.offset_sp(offset);
+  }
+
+  fn ld_hl_sp(&mut self, offset: u8) {
+    let result = self.offset_sp(offset);
+    self.reg.set_16bit(&RegCode::HL, (result >> 8) as u8, (result & 0x00FF) as u8);
+  }
+}
+
+impl OpcodeDecoder<'_> {
+  // Shared by ADD SP,e8 and LD HL,SP+e8: the carries come from the unsigned
+  // low byte addition even though the offset itself is signed.
+  fn offset_sp(&mut self, offset: u8) -> u16 {
+    let sp = self.reg.sp;
+    self.reg.set_flag(&Flag::Zero, false);
+    self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, (sp & 0xF) + (offset as u16 & 0xF) > 0xF);
+    self.reg.set_flag(&Flag::CarryFlag, (sp & 0xFF) + (offset as u16) > 0xFF);
+    return sp.wrapping_add((offset as i8) as u16);
+  }
+}
+
+impl Accumulator for OpcodeDecoder<'_> {
+  fn daa(&mut self) {
+    let mut a = self.reg.a;
+    let mut carry = self.reg.check_flag(&Flag::CarryFlag);
+    if self.reg.check_flag(&Flag::AddSubBCD) {
+      if carry { a = a.wrapping_sub(0x60); }
+      if self.reg.check_flag(&Flag::HalfCarryFlagBCD) { a = a.wrapping_sub(0x06); }
+    } else {
+      if carry || a > 0x99 {
+        a = a.wrapping_add(0x60);
+        carry = true;
+      }
+      if self.reg.check_flag(&Flag::HalfCarryFlagBCD) || (a & 0xF) > 0x9 {
+        a = a.wrapping_add(0x06);
+      }
+    }
+    self.reg.set_flag(&Flag::Zero, a == 0);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
+    self.reg.set_flag(&Flag::CarryFlag, carry);
+    self.reg.set_8bit(&RegCode::A, a);
+  }
+
+  fn cpl(&mut self) {
+    self.reg.a = !self.reg.a;
+    self.reg.set_flag(&Flag::AddSubBCD, true);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, true);
+  }
+
+  fn scf(&mut self) {
+    self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
+    self.reg.set_flag(&Flag::CarryFlag, true);
+  }
+
+  fn ccf(&mut self) {
+    let carry = self.reg.check_flag(&Flag::CarryFlag);
+    self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
+    self.reg.set_flag(&Flag::CarryFlag, !carry);
+  }
+}
+
 impl IncDec<RegCode> for OpcodeDecoder<'_> {
   fn inc(&mut self, source: RegCode) {
     match source {
@@ -198,60 +508,115 @@ impl IncDec<usize> for OpcodeDecoder<'_> {
     self.reg.set_flag(&Flag::Zero, byte == 0);
     self.reg.set_flag(&Flag::HalfCarryFlagBCD, (byte & 0xF) == 0);
     self.reg.set_flag(&Flag::AddSubBCD, false);
-    match self.bus.save(pointer, byte) {
-        Ok(_) => (),
-        Err(_) => panic!("failed")
-    }
+    self.write_byte(pointer, byte);
   }
 
   fn dec(&mut self, pointer: usize) {
     let byte = self.bus.fetch(pointer).wrapping_sub(1);
     self.reg.set_flag(&Flag::Zero, byte == 0);
-    self.reg.set_flag(&Flag::HalfCarryFlagBCD, (byte & 0xF) == 0);
-    self.reg.set_flag(&Flag::AddSubBCD, false);
-    match self.bus.save(pointer, byte) {
-        Ok(_) => (),
-        Err(_) => panic!("failed")
-    }
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, (byte & 0xF) == 0xF);
+    self.reg.set_flag(&Flag::AddSubBCD, true);
+    self.write_byte(pointer, byte);
   }
 }
 
-impl Rotate for OpcodeDecoder<'_> {
-  fn rlc(&mut self, r: RegCode) {
-    let c = self.reg.get_8bit(&r);
-    let mc = (c & (BitMasks::Bit7) as u8) >> 7;
-    let new_value = (self.reg.get_8bit(&r) << 1) | mc;
-    
-    self.reg.set_flag(&Flag::CarryFlag, mc != 0);
-    self.reg.set_flag(&Flag::Zero, new_value == 0);
+impl Operand<RegCode> for OpcodeDecoder<'_> {
+  fn read_operand(&mut self, code: &RegCode) -> u8 {
+    self.reg.get_8bit(code)
+  }
+
+  fn write_operand(&mut self, code: &RegCode, byte: u8) {
+    self.reg.set_8bit(code, byte);
+  }
+}
+
+impl Operand<usize> for OpcodeDecoder<'_> {
+  fn read_operand(&mut self, addr: &usize) -> u8 {
+    self.bus.fetch(*addr)
+  }
+
+  fn write_operand(&mut self, addr: &usize, byte: u8) {
+    self.write_byte(*addr, byte);
+  }
+}
+
+impl OpcodeDecoder<'_> {
+  fn shift_flags(&mut self, result: u8, carry: bool) -> u8 {
+    self.reg.set_flag(&Flag::CarryFlag, carry);
+    self.reg.set_flag(&Flag::Zero, result == 0);
     self.reg.set_flag(&Flag::AddSubBCD, false);
     self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
+    return result;
+  }
+}
 
-    self.reg.set_8bit(&r, new_value);
+impl<S> Rotate<S> for OpcodeDecoder<'_> where Self: Operand<S> {
+  fn rlc(&mut self, target: S) {
+    let c = self.read_operand(&target);
+    let mc = (c & (BitMasks::Bit7) as u8) >> 7;
+    let new_value = self.shift_flags((c << 1) | mc, mc != 0);
+    self.write_operand(&target, new_value);
   }
 
-  fn rl(&mut self, r: RegCode) {
-    let c = self.reg.get_8bit(&r);
+  fn rl(&mut self, target: S) {
+    let c = self.read_operand(&target);
     let mc = (c & (BitMasks::Bit7) as u8) >> 7;
     let old_carry: u8 = match self.reg.check_flag(&Flag::CarryFlag) {
       true => 1,
       false => 0,
     };
+    let new_value = self.shift_flags((c << 1) | old_carry, mc != 0);
+    self.write_operand(&target, new_value);
+  }
 
-    let new_value = (self.reg.get_8bit(&r) << 1) | old_carry;
-    
-    self.reg.set_flag(&Flag::CarryFlag, mc != 0);
-    self.reg.set_flag(&Flag::Zero, new_value == 0);
-    self.reg.set_flag(&Flag::AddSubBCD, false);
-    self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
+  fn rrc(&mut self, target: S) {
+    let c = self.read_operand(&target);
+    let lc = c & (BitMasks::Bit0 as u8);
+    let new_value = self.shift_flags((c >> 1) | (lc << 7), lc != 0);
+    self.write_operand(&target, new_value);
+  }
 
-    self.reg.set_8bit(&r, new_value);
+  fn rr(&mut self, target: S) {
+    let c = self.read_operand(&target);
+    let lc = c & (BitMasks::Bit0 as u8);
+    let old_carry: u8 = match self.reg.check_flag(&Flag::CarryFlag) {
+      true => 1,
+      false => 0,
+    };
+    let new_value = self.shift_flags((c >> 1) | (old_carry << 7), lc != 0);
+    self.write_operand(&target, new_value);
+  }
+
+  fn sla(&mut self, target: S) {
+    let c = self.read_operand(&target);
+    let new_value = self.shift_flags(c << 1, c & (BitMasks::Bit7 as u8) != 0);
+    self.write_operand(&target, new_value);
+  }
+
+  fn sra(&mut self, target: S) {
+    let c = self.read_operand(&target);
+    let msb = c & (BitMasks::Bit7 as u8);
+    let new_value = self.shift_flags((c >> 1) | msb, c & (BitMasks::Bit0 as u8) != 0);
+    self.write_operand(&target, new_value);
+  }
+
+  fn srl(&mut self, target: S) {
+    let c = self.read_operand(&target);
+    let new_value = self.shift_flags(c >> 1, c & (BitMasks::Bit0 as u8) != 0);
+    self.write_operand(&target, new_value);
+  }
+
+  fn swap(&mut self, target: S) {
+    let c = self.read_operand(&target);
+    let new_value = self.shift_flags((c << 4) | (c >> 4), false);
+    self.write_operand(&target, new_value);
   }
 }
 
 impl Jump for OpcodeDecoder<'_> {
   fn jp(&mut self, condition: bool, pointer: usize) {
     if condition {
+      self.branch_taken = true;
       self.message_buffer.push((LogEvents::Register, format!("[JP] Setting PC to {:#06x}", pointer)));
       self.reg.set_pc(pointer);
     }
@@ -259,8 +624,9 @@ impl Jump for OpcodeDecoder<'_> {
 
   fn jr(&mut self, condition: bool, byte: u8) {
     let current_pc = self.reg.pc as u16;
-    let next = (current_pc as i32 + (byte as i8) as i32) as usize;
-        if condition {
+    let next = current_pc.wrapping_add((byte as i8) as u16) as usize;
+    if condition {
+      self.branch_taken = true;
       self.reg.set_pc(next);
     }
   }
@@ -269,41 +635,85 @@ impl Jump for OpcodeDecoder<'_> {
 impl Stack for OpcodeDecoder<'_> {
   fn call(&mut self, condition: bool, next: usize) {
     if condition {
-      self.reg.sp = self.reg.sp - 2;
-      self.bus.save(self.reg.sp as usize, (self.reg.pc & 0x00FF) as u8);
-      self.bus.save((self.reg.sp+1) as usize, ((self.reg.pc & 0xFF00) >> 8) as u8);
+      self.push_word(self.reg.pc as u16);
       self.jp(true, next);
     }
   }
 
-  fn ret(&mut self) {
-    let left_byte = self.bus.fetch((self.reg.sp+1) as usize);
-    let right_byte = self.bus.fetch(self.reg.sp as usize);
-    self.reg.set_16bit(&RegCode::PC, left_byte, right_byte);
+  fn ret(&mut self, condition: bool) {
+    if condition {
+      self.branch_taken = true;
+      let addr = self.pop_word();
+      self.reg.set_pc(addr as usize);
+    }
+  }
+
+  fn rst(&mut self, vector: usize) {
+    self.call(true, vector);
   }
 
   fn push(&mut self, reg: RegCode) {
-    self.reg.sp -= 2;
     let data = self.reg.get_16bit(&reg);
-    self.bus.save(self.reg.sp as usize, ((data & 0xFF00) >> 8) as u8);
-    self.bus.save((self.reg.sp + 1) as usize, (data & 0x00FF) as u8 );
-    // let right_byte = self.bus.fetch((self.reg.sp+1) as usize);
-    // let left_byte = self.bus.fetch(self.reg.sp as usize);
-    // self.reg.set_16bit(&reg, left_byte, right_byte);
+    self.push_word(data);
   }
 
   fn pop(&mut self, reg: RegCode) {
-    let right_byte = self.bus.fetch((self.reg.sp+1) as usize);
-    let left_byte = self.bus.fetch(self.reg.sp as usize);
-    self.reg.set_16bit(&reg, left_byte, right_byte);
-    self.reg.sp += 2;
+    let data = self.pop_word();
+    let right_byte = match reg {
+      // The lower nibble of F is hardwired to zero
+      RegCode::AF => (data & 0x00F0) as u8,
+      _ => (data & 0x00FF) as u8,
+    };
+    self.reg.set_16bit(&reg, (data >> 8) as u8, right_byte);
   }
 }
 
-impl BitCheck for OpcodeDecoder<'_> {
-  fn check_bit(&mut self, code: RegCode, mask: u8) {
-    self.reg.set_flag(&Flag::Zero, self.reg.get_8bit(&code) & mask != mask);
-    self.reg.set_flag(&Flag::AddSubBCD, true);
-    self.reg.set_flag(&Flag::HalfCarryFlagBCD, false);
+impl<S> BitCheck<S> for OpcodeDecoder<'_> where Self: Operand<S> {
+  fn check_bit(&mut self, target: S, mask: u8) {
+    let byte = self.read_operand(&target);
+    self.reg.set_flag(&Flag::Zero, byte & mask != mask);
+    self.reg.set_flag(&Flag::AddSubBCD, false);
+    self.reg.set_flag(&Flag::HalfCarryFlagBCD, true);
+  }
+
+  fn reset_bit(&mut self, target: S, mask: u8) {
+    let byte = self.read_operand(&target);
+    self.write_operand(&target, byte & !mask);
+  }
+
+  fn set_bit(&mut self, target: S, mask: u8) {
+    let byte = self.read_operand(&target);
+    self.write_operand(&target, byte | mask);
+  }
+}
+
+impl InterruptControl for OpcodeDecoder<'_> {
+  fn di(&mut self) {
+    self.ime.disable();
+  }
+
+  fn ei(&mut self) {
+    self.ime.schedule_enable();
+  }
+
+  fn reti(&mut self) {
+    self.ret(true);
+    self.ime.enable();
+  }
+}
+
+impl LowPower for OpcodeDecoder<'_> {
+  fn halt(&mut self) {
+    if !self.ime.enabled && self.bus.interrupts.pending().is_some() {
+      *self.state = CpuState::HaltBug;
+    } else {
+      *self.state = CpuState::Halted;
+    }
+  }
+
+  fn stop(&mut self) {
+    // Any write to DIV resets it
+    self.write_byte(0xFF04, 0x00);
+    *self.state = CpuState::Stopped;
   }
 }
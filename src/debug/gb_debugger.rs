@@ -63,15 +63,15 @@ impl Debuggable for Gameboy {
             let addr = self.state.add_breakpoint(&command);
             self.terminal.print_message(MessageType::Good, &format!("Breakpoint added @ {:#04x}", addr));
           },
-          super::input::CommandType::ShowRegister => self.terminal.print_registers(&self.cpu.reg),
+          super::input::CommandType::ShowRegister => {
+            self.terminal.print_registers(&self.cpu.reg);
+            self.terminal.print_message(MessageType::Normal, &format!("CPU state: {:?}, IME: {}", self.cpu.state, self.cpu.ime.enabled));
+          },
           super::input::CommandType::ShowMemory => {
             let since = self.state.args_to_u16(&command) as usize;
-            // let rem = 
-            let rem = match self.mmu.data.len() - since {
-              x if x > 5 => 5,
-              x => x
-            };
-            self.terminal.print_memory(since - 5, &self.mmu.data[(since-5)..(since+rem)], 5);
+            let start = since.saturating_sub(5);
+            let end = std::cmp::min(since + 5, 0xFFFF);
+            self.terminal.print_memory(start, &self.mmu.peek_range(start, end), since - start);
           },
           super::input::CommandType::Local => {
             let lower_bound = match self.cpu.reg.pc {
@@ -79,7 +79,8 @@ impl Debuggable for Gameboy {
               x => x - 20,
             };
             let center = self.cpu.reg.pc - lower_bound;
-            self.terminal.print_hextable(&self.mmu.data[lower_bound..self.cpu.reg.pc+20], 
+            let upper_bound = std::cmp::min(self.cpu.reg.pc + 20, 0xFFFF);
+            self.terminal.print_hextable(&self.mmu.peek_range(lower_bound, upper_bound),
               center, 0)
           },
           super::input::CommandType::Print => self.terminal.print_message(MessageType::Bad, "Command not allowed"),
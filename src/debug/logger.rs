@@ -25,6 +25,11 @@ pub enum LogEvents {
   DebugLoggerOn,
   DebugLoggerOff,
   Register,
+  Interrupt,
+  Rumble,
+  Save,
+  /// The CPU hung and emulation stopped
+  Lockup,
 }
 
 impl Clone for LogEvents {
@@ -61,8 +66,10 @@ impl Logger {
     return LoggerClient::new(client_chin);
   }
 
-  pub fn poll_message(&mut self) -> Result<(),String> {
+  /// Handles the next message. Returns false once the emulator asked the logger to exit.
+  pub fn poll_message(&mut self) -> Result<bool,String> {
     match self.chout.recv() {
+      Ok((LogEvents::Exit, _)) => Ok(false),
       Ok(m) => Ok(self.process_message(m)),
       Err(e) => Err(format!("failed to poll message {}", e))
     }
@@ -77,23 +84,29 @@ impl Logger {
     println!("{}", msg);
   }
 
-  fn print_debug(&mut self, msg: String) {
-    if self.debug_logger {
+  fn print_debug(&mut self, event: LogEvents, msg: String) {
+    if (self.debug_logger || self.cfg.logger_enabled) && self.cfg.log_flags.allows(event) {
       println!("{}", msg);
     }
   }
 
-  fn process_message(&mut self, msg: LogMessage) {
+  fn process_message(&mut self, msg: LogMessage) -> bool {
     match msg.0 {
-      LogEvents::VramSave => self.print_debug(msg.1),
-      LogEvents::Tick => self.print_debug(msg.1),
-      LogEvents::MemoryFetch => self.print_debug(msg.1),
-      LogEvents::MemorySave => self.print_debug(msg.1),
-      LogEvents::Register => self.print_debug(msg.1),
+      LogEvents::VramSave
+      | LogEvents::Tick
+      | LogEvents::MemoryFetch
+      | LogEvents::MemorySave
+      | LogEvents::Decoding
+      | LogEvents::Snapshot
+      | LogEvents::Register
+      | LogEvents::Interrupt
+      | LogEvents::Rumble => self.print_debug(msg.0, msg.1),
+      LogEvents::Save | LogEvents::Lockup => self.log_terminal(msg.1),
       LogEvents::DebugLoggerOn => self.debug_logger = true,
       LogEvents::DebugLoggerOff => self.debug_logger = false,
       _ => ()
     };
+    return true;
   }
 }
 
@@ -0,0 +1,96 @@
+use crate::mmu::OAM_SIZE;
+
+/// M-cycles between the write to 0xFF46 and the first byte copied
+const STARTUP_CYCLES: u8 = 1;
+
+/// OAM DMA, started by writing a source page to 0xFF46. Copies one byte per
+/// M-cycle from `page << 8` into OAM, 160 in total.
+pub struct OamDma {
+  /// Last value written to 0xFF46, which is also what reads return
+  pub register: u8,
+  source: u16,
+  /// Next byte to copy, `None` when no transfer is running
+  progress: Option<usize>,
+  /// Transfer written to 0xFF46 and the M-cycles left before it takes over
+  pending: Option<(u16, u8)>,
+}
+
+impl OamDma {
+  pub fn new() -> Self {
+    OamDma {
+      register: 0xFF,
+      source: 0x0000,
+      progress: None,
+      pending: None,
+    }
+  }
+
+  /// Schedules a transfer from `page`. A transfer already running keeps going
+  /// until the new one starts, so the bus stays locked across a restart.
+  pub fn start(&mut self, page: u8) {
+    self.register = page;
+    // Pages 0xE0-0xFF read from echo RAM instead of OAM and the registers
+    let source = (page as u16) << 8;
+    let source = if source >= 0xE000 { source - 0x2000 } else { source };
+    self.pending = Some((source, STARTUP_CYCLES));
+  }
+
+  /// Whether the CPU is cut off from everything but HRAM and the registers
+  pub fn is_active(&self) -> bool {
+    return self.progress.is_some();
+  }
+
+  /// Advances by one M-cycle. Returns the source address and OAM offset of
+  /// the byte to copy during it, if any.
+  pub fn step(&mut self) -> Option<(usize, usize)> {
+    let copy = self.progress.map(|offset| (self.source as usize + offset, offset));
+    self.progress = match self.progress {
+      Some(offset) if offset + 1 < OAM_SIZE => Some(offset + 1),
+      _ => None,
+    };
+
+    if let Some((source, cycles)) = self.pending {
+      if cycles > 1 {
+        self.pending = Some((source, cycles - 1));
+      } else {
+        self.pending = None;
+        self.source = source;
+        self.progress = Some(0);
+      }
+    }
+    return copy;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn copies_160_bytes_after_one_cycle() {
+    let mut dma = OamDma::new();
+    dma.start(0xC1);
+    assert_eq!(dma.register, 0xC1);
+    assert_eq!(dma.step(), None);
+    assert!(dma.is_active());
+    for offset in 0..OAM_SIZE {
+      assert_eq!(dma.step(), Some((0xC100 + offset, offset)));
+    }
+    assert!(!dma.is_active());
+    assert_eq!(dma.step(), None);
+  }
+
+  #[test]
+  fn restarting_resumes_from_the_first_byte_without_unlocking() {
+    let mut dma = OamDma::new();
+    dma.start(0xC0);
+    for _ in 0..51 {
+      dma.step();
+    }
+    dma.start(0xFE);
+    // The old transfer still owns the cycle after the write
+    assert_eq!(dma.step(), Some((0xC032, 0x32)));
+    assert!(dma.is_active());
+    assert_eq!(dma.step(), Some((0xDE00, 0)));
+  }
+}
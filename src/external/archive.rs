@@ -0,0 +1,159 @@
+use std::io::{Cursor, Read};
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use super::cartridge::MAX_ROM_SIZE;
+use super::error::LoadError;
+
+const GZIP_MAGIC: [u8;2] = [0x1F, 0x8B];
+const ZIP_MAGIC: [u8;4] = [0x50, 0x4B, 0x03, 0x04];
+const ZIP_EMPTY_MAGIC: [u8;4] = [0x50, 0x4B, 0x05, 0x06];
+
+/// Container the ROM image arrived in, detected by its leading magic bytes
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum Container {
+    Raw,
+    Gzip,
+    Zip,
+}
+
+impl Container {
+    pub fn detect(raw: &[u8]) -> Container {
+        if raw.starts_with(&GZIP_MAGIC) {
+            return Container::Gzip;
+        }
+        if raw.starts_with(&ZIP_MAGIC) || raw.starts_with(&ZIP_EMPTY_MAGIC) {
+            return Container::Zip;
+        }
+        return Container::Raw;
+    }
+}
+
+/// Returns the ROM image held by `raw`, decompressing gzip streams and zip
+/// archives. From a zip the `entry` is taken when given, the first .gb or .gbc
+/// file otherwise. Anything else is passed through untouched.
+pub fn unpack(raw: Vec<u8>, entry: Option<&str>) -> Result<Vec<u8>, LoadError> {
+    return match Container::detect(&raw) {
+        Container::Raw => Ok(raw),
+        Container::Gzip => read_capped(GzDecoder::new(raw.as_slice()), "gzip stream"),
+        Container::Zip => unpack_zip(raw, entry),
+    }
+}
+
+/// Decompresses at most one byte past `MAX_ROM_SIZE` so a small archive can't
+/// inflate into gigabytes
+fn read_capped<R: Read>(reader: R, what: &str) -> Result<Vec<u8>, LoadError> {
+    let mut buf = Vec::new();
+    reader
+        .take(MAX_ROM_SIZE as u64 + 1)
+        .read_to_end(&mut buf)
+        .map_err(|why| LoadError::Archive(format!("couldn't inflate {}: {}", what, why)))?;
+    if buf.len() > MAX_ROM_SIZE {
+        return Err(LoadError::Archive(format!("{} inflates past the {} byte ROM limit", what, MAX_ROM_SIZE)));
+    }
+    return Ok(buf);
+}
+
+fn is_rom_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    return name.ends_with(".gb") || name.ends_with(".gbc");
+}
+
+/// Name of the first .gb/.gbc file in central directory order
+fn first_rom_name(archive: &mut ZipArchive<Cursor<Vec<u8>>>) -> Result<String, LoadError> {
+    for i in 0..archive.len() {
+        let file = archive
+            .by_index_raw(i)
+            .map_err(|why| LoadError::Archive(format!("corrupt zip archive: {}", why)))?;
+        if file.is_file() && is_rom_name(file.name()) {
+            return Ok(String::from(file.name()));
+        }
+    }
+    return Err(LoadError::NoRomInArchive { entry: None });
+}
+
+fn unpack_zip(raw: Vec<u8>, entry: Option<&str>) -> Result<Vec<u8>, LoadError> {
+    let corrupt = |why: zip::result::ZipError| LoadError::Archive(format!("corrupt zip archive: {}", why));
+    let mut archive = ZipArchive::new(Cursor::new(raw)).map_err(corrupt)?;
+
+    let name = match entry {
+        Some(name) => String::from(name),
+        None => first_rom_name(&mut archive)?,
+    };
+
+    let file = match archive.by_name(&name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) =>
+            return Err(LoadError::NoRomInArchive { entry: Some(name) }),
+        Err(why) => return Err(corrupt(why)),
+    };
+    return read_capped(file, &name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn passes_raw_images_through() {
+        let rom = vec![0x00, 0xC3, 0x50, 0x01];
+        assert_eq!(Container::detect(&rom), Container::Raw);
+        assert_eq!(unpack(rom.clone(), None).unwrap(), rom);
+    }
+
+    #[test]
+    fn inflates_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0xAB; 0x200]).unwrap();
+        let packed = encoder.finish().unwrap();
+
+        assert_eq!(Container::detect(&packed), Container::Gzip);
+        assert_eq!(unpack(packed, None).unwrap(), vec![0xAB; 0x200]);
+    }
+
+    #[test]
+    fn refuses_to_inflate_past_the_rom_limit() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&vec![0; MAX_ROM_SIZE + 1]).unwrap();
+        let packed = encoder.finish().unwrap();
+        assert!(matches!(unpack(packed, None), Err(LoadError::Archive(_))));
+
+        let packed = zip(&[("big.gb", &vec![0; MAX_ROM_SIZE + 1])]);
+        assert!(matches!(unpack(packed, None), Err(LoadError::Archive(_))));
+        let packed = zip(&[("max.gb", &vec![0; MAX_ROM_SIZE])]);
+        assert_eq!(unpack(packed, None).unwrap().len(), MAX_ROM_SIZE);
+    }
+
+    #[test]
+    fn picks_first_rom_from_zip() {
+        let packed = zip(&[("readme.txt", b"hi"), ("b.GBC", &[2; 4]), ("a.gb", &[1; 4])]);
+        assert_eq!(Container::detect(&packed), Container::Zip);
+        assert_eq!(unpack(packed.clone(), None).unwrap(), vec![2; 4]);
+        assert_eq!(unpack(packed, Some("a.gb")).unwrap(), vec![1; 4]);
+    }
+
+    #[test]
+    fn reports_missing_zip_entries() {
+        let packed = zip(&[("readme.txt", b"hi")]);
+        assert!(matches!(unpack(packed.clone(), None), Err(LoadError::NoRomInArchive { entry: None })));
+        assert!(matches!(
+            unpack(packed, Some("game.gb")),
+            Err(LoadError::NoRomInArchive { entry: Some(_) })
+        ));
+    }
+}
@@ -1,9 +1,14 @@
 use std::io::Read;
 use std::fs::File;
+use std::fmt;
+use super::archive;
+use super::error::LoadError;
+use super::licensee;
+use super::patch;
 
 #[derive(PartialEq)]
 #[derive(Debug)]
-enum CGB_Flag {
+pub enum CGB_Flag {
     RetroCompatible,
     NonRetroCompatible,
     Unkown,
@@ -19,9 +24,19 @@ impl CGB_Flag {
     }
 }
 
+impl fmt::Display for CGB_Flag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f.write_str(match self {
+            CGB_Flag::RetroCompatible => "CGB enhanced, DMG compatible",
+            CGB_Flag::NonRetroCompatible => "CGB only",
+            CGB_Flag::Unkown => "DMG only",
+        });
+    }
+}
+
 #[derive(PartialEq)]
 #[derive(Debug)]
-enum SGB_Flag {
+pub enum SGB_Flag {
     Support,
     NoSupport,
     Unkown
@@ -37,9 +52,19 @@ impl SGB_Flag {
     }
 }
 
+impl fmt::Display for SGB_Flag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f.write_str(match self {
+            SGB_Flag::Support => "supported",
+            SGB_Flag::NoSupport => "not supported",
+            SGB_Flag::Unkown => "unknown",
+        });
+    }
+}
+
 #[derive(PartialEq)]
 #[derive(Debug)]
-enum CartridgeType {
+pub enum CartridgeType {
     ROM_ONLY,
     MBC1,
     MBC1_RAM,
@@ -72,7 +97,24 @@ enum CartridgeType {
 }
 
 impl CartridgeType {
-    fn map(cartridge_type: u8) -> CartridgeType {
+    pub fn has_battery(&self) -> bool {
+        return match self {
+            CartridgeType::MBC1_RAM_BATTERY
+            | CartridgeType::MBC2_BATTERY
+            | CartridgeType::ROM_RAM_BATTERY
+            | CartridgeType::MMM01_RAM_BATTERY
+            | CartridgeType::MBC3_TIMER_BATTERY
+            | CartridgeType::MBC3_TIMER_RAM_BATTERY
+            | CartridgeType::MBC3_RAM_BATTERY
+            | CartridgeType::MBC5_RAM_BATTERY
+            | CartridgeType::MBC5_RUMBLE_RAM_BATTERY
+            | CartridgeType::MBC7_SENSOR_RUMBLE_RAM_BATTERY
+            | CartridgeType::HUC1_RAM_BATTERY => true,
+            _ => false,
+        }
+    }
+
+    pub fn map(cartridge_type: u8) -> CartridgeType {
         return match cartridge_type {
             0x00 => CartridgeType::ROM_ONLY,
             0x01 => CartridgeType::MBC1,
@@ -109,7 +151,7 @@ impl CartridgeType {
 
 #[derive(PartialEq)]
 #[derive(Debug)]
-enum RomSize {
+pub enum RomSize {
     _32_KByte_2_banks,
     _64_KByte_4_banks,
     _128_KByte_8_banks,
@@ -126,6 +168,28 @@ enum RomSize {
 }
 
 impl RomSize {
+    pub fn bytes(&self) -> usize {
+        return self.banks() * 0x4000;
+    }
+
+    pub fn banks(&self) -> usize {
+        return match self {
+            RomSize::_32_KByte_2_banks => 2,
+            RomSize::_64_KByte_4_banks => 4,
+            RomSize::_128_KByte_8_banks => 8,
+            RomSize::_256_KByte_16_banks => 16,
+            RomSize::_512_KByte_32_banks => 32,
+            RomSize::_1_MByte_64_banks => 64,
+            RomSize::_2_MByte_128_banks => 128,
+            RomSize::_4_MByte_256_banks => 256,
+            RomSize::_8_MByte_512_banks => 512,
+            RomSize::_1_1_MByte_72_banks => 72,
+            RomSize::_1_2_MByte_80_banks => 80,
+            RomSize::_1_5_MByte_96_banks => 96,
+            RomSize::Unkown => 0,
+        }
+    }
+
     fn map(byte: u8) -> RomSize {
         return match byte {
             0x00 => RomSize::_32_KByte_2_banks,
@@ -147,7 +211,7 @@ impl RomSize {
 
 #[derive(PartialEq)]
 #[derive(Debug)]
-enum RamSize {
+pub enum RamSize {
     NONE,
     _2_KBytes,
     _8_KBytes,
@@ -158,6 +222,18 @@ enum RamSize {
 }
 
 impl RamSize {
+    pub fn bytes(&self) -> usize {
+        return match self {
+            RamSize::NONE => 0,
+            RamSize::_2_KBytes => 0x800,
+            RamSize::_8_KBytes => 0x2000,
+            RamSize::_32_KBytes => 0x8000,
+            RamSize::_128_KBytes => 0x20000,
+            RamSize::_64_KBytes => 0x10000,
+            RamSize::Unkown => 0,
+        }
+    }
+
     fn map(byte: u8) -> RamSize {
         return match byte {
             0x00 => RamSize::NONE,
@@ -173,7 +249,7 @@ impl RamSize {
 
 #[derive(PartialEq)]
 #[derive(Debug)]
-enum DestinationCode {
+pub enum DestinationCode {
     Japanese,
     NonJapanese,
     Unkown
@@ -189,11 +265,122 @@ impl DestinationCode {
     }
 }
 
+impl fmt::Display for DestinationCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f.write_str(match self {
+            DestinationCode::Japanese => "Japan",
+            DestinationCode::NonJapanese => "Overseas",
+            DestinationCode::Unkown => "unknown",
+        });
+    }
+}
+
+/// Header area holding the Nintendo logo checked by the boot rom
+pub const LOGO_START: usize = 0x104;
+pub const LOGO_SIZE: usize = 0x30;
+
+/// First byte past the cartridge header
+pub const HEADER_END: usize = 0x150;
+/// Biggest ROM a header can declare, 512 banks
+pub const MAX_ROM_SIZE: usize = 0x800000;
+
+pub const NINTENDO_LOGO: [u8;LOGO_SIZE] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83,
+    0x00, 0x0C, 0x00, 0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E,
+    0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63,
+    0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Outcome of a single header check. `expected` is what the content says the
+/// value should be, `actual` is what the header holds.
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum Check<T> {
+    Ok,
+    Mismatch { expected: T, actual: T },
+}
+
+impl<T: PartialEq> Check<T> {
+    fn compare(expected: T, actual: T) -> Check<T> {
+        return if expected == actual { Check::Ok } else { Check::Mismatch { expected, actual } };
+    }
+
+    pub fn is_ok(&self) -> bool {
+        return match self {
+            Check::Ok => true,
+            Check::Mismatch { .. } => false,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub struct VerificationReport {
+    pub logo: Check<[u8;LOGO_SIZE]>,
+    pub header_checksum: Check<u8>,
+    pub global_checksum: Check<u16>,
+    /// Image length against the ROM size byte. Overdumps and homebrew often
+    /// disagree and run fine, so only strict mode cares.
+    pub rom_size: Check<usize>,
+}
+
+impl VerificationReport {
+    pub fn is_ok(&self) -> bool {
+        return self.logo.is_ok()
+            && self.header_checksum.is_ok()
+            && self.global_checksum.is_ok()
+            && self.rom_size.is_ok();
+    }
+
+    /// The boot rom locks up on a bad logo or header checksum, the global
+    /// checksum is never looked at by the hardware
+    pub fn is_bootable(&self) -> bool {
+        return self.logo.is_ok() && self.header_checksum.is_ok();
+    }
+
+    /// What strict mode makes of the report: a size mismatch on its own
+    /// error, any other failed check as the whole report
+    pub fn strict(self) -> Result<(), LoadError> {
+        if let Check::Mismatch { expected, actual } = self.rom_size {
+            return Err(LoadError::SizeMismatch { header: actual, file: expected });
+        }
+        if !self.is_ok() {
+            return Err(LoadError::InvalidHeader(Box::new(self)));
+        }
+        return Ok(());
+    }
+
+    /// One human readable line per failed check
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if let Check::Mismatch { expected, actual } = &self.logo {
+            let offset = expected.iter().zip(actual.iter()).position(|(e, a)| e != a).unwrap_or(0);
+            problems.push(format!(
+                "Nintendo logo mismatch at {:#06x}: expected {:#04x}, found {:#04x}",
+                LOGO_START + offset, expected[offset], actual[offset]
+            ));
+        }
+        if let Check::Mismatch { expected, actual } = &self.header_checksum {
+            problems.push(format!("Header checksum mismatch: expected {:#04x}, found {:#04x}", expected, actual));
+        }
+        if let Check::Mismatch { expected, actual } = &self.global_checksum {
+            problems.push(format!("Global checksum mismatch: expected {:#06x}, found {:#06x}", expected, actual));
+        }
+        if let Check::Mismatch { expected, actual } = &self.rom_size {
+            problems.push(format!("ROM size mismatch: header declares {} bytes but the image is {} bytes", actual, expected));
+        }
+        return problems;
+    }
+}
+
 pub struct Cartridge {
-    // title: String,
+    entry_point: [u8;4],
+    logo: [u8;LOGO_SIZE],
+    title: String,
     manufacturer_code: [u8;4],
     cgb_flag: CGB_Flag,
     license_code: [u8;2],
+    old_license_code: u8,
     sgb_flag: SGB_Flag,
     cartridge_type: CartridgeType,
     rom_size: RomSize,
@@ -202,7 +389,27 @@ pub struct Cartridge {
     rom_version_num: u8,
     header_checksum: u8,
     global_checksum: u16,
-    pub content: Vec<u8>
+    pub content: Vec<u8>,
+    /// File the cartridge was read from, used to locate its save file
+    pub path: Option<String>,
+    /// Patch applied on top of the file content
+    pub patch: Option<String>,
+}
+
+/// How `Cartridge::load` turns a file into a cartridge
+pub struct LoadOptions {
+    /// Zip entry to use instead of the first .gb/.gbc file
+    pub entry: Option<String>,
+    /// IPS/UPS/BPS patch to apply, overriding the lookup next to the ROM
+    pub patch: Option<String>,
+    /// Apply a .bps, .ups or .ips file sharing the ROM's name when present
+    pub auto_patch: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        return LoadOptions { entry: None, patch: None, auto_patch: true };
+    }
 }
 
 pub fn _8bit_to_16bit(left: u8, right: u8) -> u16 {
@@ -211,27 +418,192 @@ pub fn _8bit_to_16bit(left: u8, right: u8) -> u16 {
 }
 
 impl Cartridge {
-    pub fn from_file(path: &str) -> Self {
-        let mut file = match File::open(path) {
-            Err(why) => panic!("Couldn't open {}: {}", path, why),
-            Ok(f) => f
+    /// Reads a raw, gzipped or zipped ROM image, applying any patch found next to it
+    pub fn from_file(path: &str) -> Result<Self, LoadError> {
+        return Cartridge::load(path, &LoadOptions::default());
+    }
+
+    pub fn load(path: &str, options: &LoadOptions) -> Result<Self, LoadError> {
+        let buf = Cartridge::read_file(path)?;
+        let mut rom = archive::unpack(buf, options.entry.as_deref())?;
+
+        let patch_path = match &options.patch {
+            Some(patch_path) => Some(patch_path.clone()),
+            None if options.auto_patch =>
+                patch::find_sibling(path).map(|p| p.to_string_lossy().into_owned()),
+            None => None,
         };
-    
+        if let Some(patch_path) = &patch_path {
+            let patch = Cartridge::read_file(patch_path)?;
+            rom = patch::apply(&rom, &patch)
+                .map_err(|error| LoadError::Patch { path: patch_path.clone(), error })?;
+        }
+
+        let mut cartridge = Cartridge::new(&rom)?;
+        cartridge.path = Some(String::from(path));
+        cartridge.patch = patch_path;
+        return Ok(cartridge);
+    }
+
+    fn read_file(path: &str) -> Result<Vec<u8>, LoadError> {
+        let mut file = File::open(path).map_err(|why| LoadError::io(path, why))?;
         let mut buf: Vec<u8> = Vec::new();
-        return match file.read_to_end(&mut buf) {
-            Err(why) => panic!("Couldn't read byte stream: {}", why),
-            Ok(size) => {
-                println!("Read {} bytes of data", size);
-                Cartridge::new(&buf)
-            }
+        file.read_to_end(&mut buf).map_err(|why| LoadError::io(path, why))?;
+        return Ok(buf);
+    }
+
+    pub fn entry_point(&self) -> &[u8;4] {
+        return &self.entry_point;
+    }
+
+    pub fn logo(&self) -> &[u8;LOGO_SIZE] {
+        return &self.logo;
+    }
+
+    pub fn title(&self) -> &str {
+        return &self.title;
+    }
+
+    /// Four letter product code, only present on CGB era cartridges
+    pub fn manufacturer_code(&self) -> Option<String> {
+        if self.cgb_flag == CGB_Flag::Unkown || !Cartridge::is_product_code(&self.manufacturer_code) {
+            return None;
+        }
+        return Some(self.manufacturer_code.iter().map(|&b| b as char).collect());
+    }
+
+    /// Licensee code as printed in the header: the two ASCII characters of the
+    /// new code when the old byte defers to it, the old byte in hex otherwise
+    pub fn licensee_code(&self) -> String {
+        if self.old_license_code == licensee::USE_NEW_LICENSEE {
+            return self.license_code.iter().map(|&b| b as char).collect();
+        }
+        return format!("{:02X}", self.old_license_code);
+    }
+
+    pub fn licensee(&self) -> &'static str {
+        let name = if self.old_license_code == licensee::USE_NEW_LICENSEE {
+            licensee::new_licensee_name(&self.licensee_code())
+        } else {
+            licensee::old_licensee_name(self.old_license_code)
+        };
+        return name.unwrap_or("Unknown");
+    }
+
+    pub fn cgb_flag(&self) -> &CGB_Flag {
+        return &self.cgb_flag;
+    }
+
+    pub fn sgb_flag(&self) -> &SGB_Flag {
+        return &self.sgb_flag;
+    }
+
+    pub fn header_checksum(&self) -> u8 {
+        return self.header_checksum;
+    }
+
+    pub fn global_checksum(&self) -> u16 {
+        return self.global_checksum;
+    }
+
+    pub fn cartridge_type(&self) -> &CartridgeType {
+        return &self.cartridge_type;
+    }
+
+    pub fn rom_size(&self) -> &RomSize {
+        return &self.rom_size;
+    }
+
+    pub fn rom_size_bytes(&self) -> usize {
+        return self.rom_size.bytes();
+    }
+
+    pub fn ram_size(&self) -> &RamSize {
+        return &self.ram_size;
+    }
+
+    pub fn ram_size_bytes(&self) -> usize {
+        return self.ram_size.bytes();
+    }
+
+    pub fn region(&self) -> &DestinationCode {
+        return &self.destination_code;
+    }
+
+    pub fn version(&self) -> u8 {
+        return self.rom_version_num;
+    }
+
+    /// Checksum over 0x134-0x14C the boot rom compares against 0x14D
+    pub fn compute_header_checksum(&self) -> u8 {
+        return self.content[0x134..=0x14C]
+            .iter()
+            .fold(0u8, |x, &b| x.wrapping_sub(b).wrapping_sub(1));
+    }
+
+    /// Sum of every byte in the ROM except the global checksum itself
+    pub fn compute_global_checksum(&self) -> u16 {
+        return self.content
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 0x14E && *i != 0x14F)
+            .fold(0u16, |sum, (_, &b)| sum.wrapping_add(b as u16));
+    }
+
+    pub fn verify(&self) -> VerificationReport {
+        return VerificationReport {
+            logo: Check::compare(NINTENDO_LOGO, self.logo),
+            header_checksum: Check::compare(self.compute_header_checksum(), self.header_checksum),
+            global_checksum: Check::compare(self.compute_global_checksum(), self.global_checksum),
+            rom_size: match self.rom_size {
+                RomSize::Unkown => Check::Ok,
+                _ => Check::compare(self.content.len(), self.rom_size.bytes()),
+            },
+        }
+    }
+
+    fn is_product_code(code: &[u8]) -> bool {
+        return code.iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit());
+    }
+
+    /// Title is 16 bytes on early cartridges, 15 once the CGB flag took the
+    /// last byte and 11 when a manufacturer code follows it
+    fn parse_title(cartridge_raw: &[u8]) -> String {
+        let end = match CGB_Flag::map(cartridge_raw[0x143]) {
+            CGB_Flag::Unkown => 0x144,
+            _ if Cartridge::is_product_code(&cartridge_raw[0x13F..0x143]) => 0x13F,
+            _ => 0x143,
         };
+        return cartridge_raw[0x134..end]
+            .iter()
+            .take_while(|&&b| b != 0)
+            .filter(|b| b.is_ascii_graphic() || **b == b' ')
+            .map(|&b| b as char)
+            .collect::<String>()
+            .trim_end()
+            .to_string();
     }
 
-    pub fn new(cartridge_raw: &Vec<u8>) -> Self {
-        return Cartridge {
-            manufacturer_code: [0;4],
+    pub fn new(cartridge_raw: &Vec<u8>) -> Result<Self, LoadError> {
+        if cartridge_raw.len() < HEADER_END {
+            return Err(LoadError::TruncatedHeader { size: cartridge_raw.len() });
+        }
+
+        let mut entry_point = [0;4];
+        entry_point.copy_from_slice(&cartridge_raw[0x100..0x104]);
+        let mut logo = [0;LOGO_SIZE];
+        logo.copy_from_slice(&cartridge_raw[LOGO_START..LOGO_START + LOGO_SIZE]);
+        let mut manufacturer_code = [0;4];
+        manufacturer_code.copy_from_slice(&cartridge_raw[0x13F..0x143]);
+
+        return Ok(Cartridge {
+            entry_point,
+            logo,
+            title: Cartridge::parse_title(cartridge_raw),
+            manufacturer_code,
             cgb_flag: CGB_Flag::map(cartridge_raw[0x143]),
-            license_code: [0;2],
+            license_code: [cartridge_raw[0x144], cartridge_raw[0x145]],
+            old_license_code: cartridge_raw[0x14B],
             sgb_flag: SGB_Flag::map(cartridge_raw[0x146]),
             cartridge_type: CartridgeType::map(cartridge_raw[0x147]),
             rom_size: RomSize::map(cartridge_raw[0x148]),
@@ -240,8 +612,29 @@ impl Cartridge {
             rom_version_num: cartridge_raw[0x14C],
             header_checksum: cartridge_raw[0x14D],
             global_checksum: _8bit_to_16bit(cartridge_raw[0x14E], cartridge_raw[0x14F]),
-            content: cartridge_raw.clone()
+            content: cartridge_raw.clone(),
+            path: None,
+            patch: None,
+        })
+    }
+}
+
+impl fmt::Display for Cartridge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Title:           {}", self.title)?;
+        if let Some(code) = self.manufacturer_code() {
+            writeln!(f, "Manufacturer:    {}", code)?;
         }
+        writeln!(f, "Licensee:        {} ({})", self.licensee(), self.licensee_code())?;
+        writeln!(f, "Cartridge type:  {:?}", self.cartridge_type)?;
+        writeln!(f, "ROM size:        {} KiB ({} banks)", self.rom_size.bytes() / 1024, self.rom_size.banks())?;
+        writeln!(f, "RAM size:        {} KiB", self.ram_size.bytes() / 1024)?;
+        writeln!(f, "CGB:             {}", self.cgb_flag)?;
+        writeln!(f, "SGB:             {}", self.sgb_flag)?;
+        writeln!(f, "Region:          {}", self.destination_code)?;
+        writeln!(f, "Version:         {}", self.rom_version_num)?;
+        writeln!(f, "Header checksum: {:#04x}", self.header_checksum)?;
+        write!(f, "Global checksum: {:#06x}", self.global_checksum)
     }
 }
 
@@ -252,7 +645,7 @@ mod tests {
     #[test]
     fn test_load_intr_01_headers() {
         let test_path = String::from("./res/test/01-special.gb");
-        let loaded = Cartridge::from_file(&test_path);
+        let loaded = Cartridge::from_file(&test_path).unwrap();
 
         assert_eq!(loaded.cgb_flag, CGB_Flag::RetroCompatible);
         assert_eq!(loaded.cartridge_type, CartridgeType::MBC1);
@@ -260,6 +653,133 @@ mod tests {
         assert_eq!(loaded.ram_size, RamSize::NONE);
         assert_eq!(loaded.header_checksum, 0x66);
         assert_eq!(loaded.global_checksum, 0x4deb);
+        assert_eq!(loaded.title(), "");
+        assert_eq!(loaded.licensee(), "None");
+        assert_eq!(loaded.rom_size_bytes(), 0x8000);
+        assert_eq!(loaded.logo()[..4], [0xCE, 0xED, 0x66, 0x66]);
+    }
+
+    #[test]
+    fn verifies_intr_01_header() {
+        let loaded = Cartridge::from_file("./res/test/01-special.gb").unwrap();
+        let report = loaded.verify();
+        assert!(report.is_ok());
+        assert!(report.problems().is_empty());
+    }
+
+    #[test]
+    fn reports_checksum_and_logo_mismatches() {
+        let mut rom = Cartridge::from_file("./res/test/01-special.gb").unwrap().content;
+        rom[0x14D] = 0x67;
+        rom[0x105] = 0x00;
+        rom[0x4000] ^= 0xFF;
+        let report = Cartridge::new(&rom).unwrap().verify();
+
+        assert_eq!(report.header_checksum, Check::Mismatch { expected: 0x66, actual: 0x67 });
+        assert!(!report.logo.is_ok());
+        assert!(!report.global_checksum.is_ok());
+        assert!(!report.is_bootable());
+        assert_eq!(report.problems().len(), 3);
+        assert!(report.problems()[0].contains("0x0105"));
+    }
+
+    #[test]
+    fn warns_about_size_mismatches() {
+        let mut rom = Cartridge::from_file("./res/test/01-special.gb").unwrap().content;
+        rom.resize(0x10000, 0xFF);
+        let report = Cartridge::new(&rom).unwrap().verify();
+
+        assert_eq!(report.rom_size, Check::Mismatch { expected: 0x10000, actual: 0x8000 });
+        assert!(report.is_bootable());
+        assert!(!report.is_ok());
+        assert!(report.problems().last().unwrap().contains("declares 32768 bytes but the image is 65536"));
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        assert!(matches!(Cartridge::new(&vec![0; 0x14F]), Err(LoadError::TruncatedHeader { size: 0x14F })));
+        assert!(matches!(Cartridge::from_file("./res/test/missing.gb"), Err(LoadError::Io { .. })));
+    }
 
+    #[test]
+    fn applies_patches_before_parsing_the_header() {
+        let dir = std::env::temp_dir().join(format!("gb-patch-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb");
+        std::fs::copy("./res/test/01-special.gb", &rom_path).unwrap();
+        let rom_path = rom_path.to_str().unwrap();
+
+        // IPS writing "PATCHED" over the title
+        let mut ips = b"PATCH\x00\x01\x34\x00\x07PATCHED".to_vec();
+        ips.extend_from_slice(b"EOF");
+        std::fs::write(dir.join("game.ips"), &ips).unwrap();
+
+        let patched = Cartridge::from_file(rom_path).unwrap();
+        assert_eq!(patched.title(), "PATCHED");
+        assert_eq!(patched.patch.as_deref(), dir.join("game.ips").to_str());
+
+        let options = LoadOptions { auto_patch: false, ..LoadOptions::default() };
+        assert_eq!(Cartridge::load(rom_path, &options).unwrap().title(), "");
+
+        // UPS with a footer that matches nothing
+        let bad_path = dir.join("bad.ups");
+        std::fs::write(&bad_path, b"UPS1\x80\x80\0\0\0\0\0\0\0\0\0\0\0\0").unwrap();
+        let options = LoadOptions { patch: Some(bad_path.to_str().unwrap().to_string()), ..LoadOptions::default() };
+        assert!(matches!(
+            Cartridge::load(rom_path, &options),
+            Err(LoadError::Patch { error: patch::PatchError::ChecksumMismatch { what: "patch", .. }, .. })
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn header(title: &[u8], cgb: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x143] = cgb;
+        rom[0x134..0x134 + title.len()].copy_from_slice(title);
+        rom
+    }
+
+    #[test]
+    fn parses_title_and_manufacturer_code() {
+        let dmg = Cartridge::new(&header(b"SIXTEEN CHARS AB", 0x00)).unwrap();
+        assert_eq!(dmg.title(), "SIXTEEN CHARS AB");
+        assert_eq!(dmg.manufacturer_code(), None);
+
+        let cgb = Cartridge::new(&header(b"POKEMON YELAPSE", 0x80)).unwrap();
+        assert_eq!(cgb.title(), "POKEMON YEL");
+        assert_eq!(cgb.manufacturer_code(), Some(String::from("APSE")));
+    }
+
+    #[test]
+    fn resolves_licensee_from_either_code() {
+        let mut rom = header(b"", 0x00);
+        rom[0x14B] = 0x01;
+        assert_eq!(Cartridge::new(&rom).unwrap().licensee(), "Nintendo");
+
+        rom[0x14B] = 0x33;
+        rom[0x144..0x146].copy_from_slice(b"A4");
+        let cartridge = Cartridge::new(&rom).unwrap();
+        assert_eq!(cartridge.licensee_code(), "A4");
+        assert_eq!(cartridge.licensee(), "Konami (Yu-Gi-Oh!)");
+    }
+
+    #[test]
+    fn exposes_sizes_region_and_version() {
+        let mut rom = header(b"TEST", 0xC0);
+        rom.resize(1024 * 1024, 0);
+        rom[0x146] = 0x03;
+        rom[0x148] = 0x05;
+        rom[0x149] = 0x03;
+        rom[0x14A] = 0x01;
+        rom[0x14C] = 0x02;
+        let cartridge = Cartridge::new(&rom).unwrap();
+        assert_eq!(*cartridge.cgb_flag(), CGB_Flag::NonRetroCompatible);
+        assert_eq!(*cartridge.sgb_flag(), SGB_Flag::Support);
+        assert_eq!(cartridge.rom_size_bytes(), 1024 * 1024);
+        assert_eq!(cartridge.ram_size_bytes(), 32 * 1024);
+        assert_eq!(*cartridge.region(), DestinationCode::NonJapanese);
+        assert_eq!(cartridge.version(), 2);
+        assert!(cartridge.to_string().contains("Title:           TEST"));
     }
 }
\ No newline at end of file
@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use super::cartridge::{CartridgeType, VerificationReport};
+use super::patch::PatchError;
+
+/// Everything that can go wrong turning files on disk into a running cartridge
+#[derive(Debug)]
+pub enum LoadError {
+    Io { path: String, source: io::Error },
+    /// Image too short to hold the 0x100-0x14F header
+    TruncatedHeader { size: usize },
+    /// Cartridge type byte at 0x147 with no memory bank controller behind it
+    UnsupportedMapper(u8),
+    /// Image bigger than its memory bank controller can address
+    RomTooLarge { size: usize, max: usize },
+    /// ROM size declared at 0x148 disagrees with the image length. Only
+    /// refused in strict mode, overdumps and homebrew run fine otherwise.
+    SizeMismatch { header: usize, file: usize },
+    /// Compressed container that could not be decoded
+    Archive(String),
+    /// Zip archive without a ROM, or without the requested entry
+    NoRomInArchive { entry: Option<String> },
+    /// IPS/UPS/BPS patch that could not be applied
+    Patch { path: String, error: PatchError },
+    /// Boot rom does not fit the 0x900 byte overlay
+    BootRomTooLarge { size: usize },
+    /// Header verification failed while running in strict mode
+    InvalidHeader(Box<VerificationReport>),
+}
+
+impl LoadError {
+    pub fn io(path: &str, source: io::Error) -> LoadError {
+        return LoadError::Io { path: String::from(path), source };
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            LoadError::Io { path, source } => write!(f, "couldn't read {}: {}", path, source),
+            LoadError::TruncatedHeader { size } =>
+                write!(f, "image is {} bytes, too short to hold a cartridge header", size),
+            LoadError::UnsupportedMapper(code) =>
+                write!(f, "unsupported cartridge type {:?} ({:#04x})", CartridgeType::map(*code), code),
+            LoadError::RomTooLarge { size, max } =>
+                write!(f, "image is {} bytes but its mapper addresses at most {}", size, max),
+            LoadError::SizeMismatch { header, file } =>
+                write!(f, "header declares {} bytes of ROM but the image is {} bytes", header, file),
+            LoadError::Archive(why) => write!(f, "{}", why),
+            LoadError::NoRomInArchive { entry: Some(name) } => write!(f, "archive has no entry named {}", name),
+            LoadError::NoRomInArchive { entry: None } => write!(f, "archive holds no .gb or .gbc file"),
+            LoadError::Patch { path, error } => write!(f, "couldn't apply patch {}: {}", path, error),
+            LoadError::BootRomTooLarge { size } =>
+                write!(f, "boot rom is {} bytes, bigger than the boot rom area", size),
+            LoadError::InvalidHeader(report) =>
+                write!(f, "invalid cartridge header: {}", report.problems().join(", ")),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        return match self {
+            LoadError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -1,2 +1,7 @@
+pub mod archive;
 pub mod cartridge;
-pub mod boot_rom_loader;
\ No newline at end of file
+pub mod error;
+pub mod licensee;
+pub mod patch;
+pub mod boot_rom_loader;
+pub mod save;
@@ -0,0 +1,446 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::cartridge::MAX_ROM_SIZE;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: usize = 0x454F46;
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Source, target and patch CRC32 closing UPS and BPS files
+const FOOTER_SIZE: usize = 12;
+
+/// Extensions looked up next to a ROM, in order of preference
+pub const PATCH_EXTENSIONS: [&str;3] = ["bps", "ups", "ips"];
+
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+impl PatchFormat {
+    pub fn detect(patch: &[u8]) -> Option<PatchFormat> {
+        if patch.starts_with(IPS_MAGIC) {
+            return Some(PatchFormat::Ips);
+        }
+        if patch.starts_with(UPS_MAGIC) {
+            return Some(PatchFormat::Ups);
+        }
+        if patch.starts_with(BPS_MAGIC) {
+            return Some(PatchFormat::Bps);
+        }
+        return None;
+    }
+}
+
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum PatchError {
+    UnknownFormat,
+    /// Patch ends early or points outside the data it edits
+    Malformed(&'static str),
+    /// CRC32 stored in a UPS/BPS footer disagrees with the data. `what` is
+    /// "source", "target" or "patch".
+    ChecksumMismatch { what: &'static str, expected: u32, actual: u32 },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            PatchError::UnknownFormat => write!(f, "not an IPS, UPS or BPS patch"),
+            PatchError::Malformed(why) => write!(f, "malformed patch: {}", why),
+            PatchError::ChecksumMismatch { what, expected, actual } => write!(
+                f, "{} checksum mismatch: expected {:08x}, found {:08x}", what, expected, actual
+            ),
+        }
+    }
+}
+
+/// First patch file sitting next to `rom_path` sharing its stem
+pub fn find_sibling(rom_path: &str) -> Option<PathBuf> {
+    return PATCH_EXTENSIONS
+        .iter()
+        .map(|ext| Path::new(rom_path).with_extension(ext))
+        .find(|path| path.is_file());
+}
+
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    return match PatchFormat::detect(patch) {
+        Some(PatchFormat::Ips) => apply_ips(rom, patch),
+        Some(PatchFormat::Ups) => apply_ups(rom, patch),
+        Some(PatchFormat::Bps) => apply_bps(rom, patch),
+        None => Err(PatchError::UnknownFormat),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        let byte = *self.data.get(self.pos).ok_or(PatchError::Malformed("unexpected end of patch"))?;
+        self.pos += 1;
+        return Ok(byte);
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        if self.data.len() - self.pos < len {
+            return Err(PatchError::Malformed("unexpected end of patch"));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        return Ok(bytes);
+    }
+
+    fn big_endian(&mut self, len: usize) -> Result<usize, PatchError> {
+        return Ok(self.bytes(len)?.iter().fold(0, |n, &b| (n << 8) | b as usize));
+    }
+
+    /// Variable length number shared by UPS and BPS
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()?;
+            number = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|n| n.checked_add(number))
+                .ok_or(PatchError::Malformed("number overflow"))?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_shl(7).ok_or(PatchError::Malformed("number overflow"))?;
+            number = number.checked_add(shift).ok_or(PatchError::Malformed("number overflow"))?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut out = rom.to_vec();
+    let mut reader = Reader { data: patch, pos: IPS_MAGIC.len() };
+    loop {
+        let offset = reader.big_endian(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let size = reader.big_endian(2)?;
+        let (len, data) = match size {
+            0 => {
+                let len = reader.big_endian(2)?;
+                (len, vec![reader.byte()?; len])
+            }
+            _ => (size, reader.bytes(size)?.to_vec()),
+        };
+        if offset + len > MAX_ROM_SIZE {
+            return Err(PatchError::Malformed("record past the largest ROM size"));
+        }
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+        out[offset..offset + len].copy_from_slice(&data);
+    }
+    // Optional truncation length following the EOF marker
+    if patch.len() - reader.pos == 3 {
+        out.truncate(reader.big_endian(3)?);
+    }
+    return Ok(out);
+}
+
+/// Splits off the CRC32 footer, checking the patch's own checksum
+fn check_footer(patch: &[u8]) -> Result<(&[u8], u32, u32), PatchError> {
+    if patch.len() < 4 + FOOTER_SIZE {
+        return Err(PatchError::Malformed("patch too short"));
+    }
+    let body_end = patch.len() - FOOTER_SIZE;
+    let crc = |at: usize| u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]]);
+    let (source_crc, target_crc, patch_crc) = (crc(body_end), crc(body_end + 4), crc(body_end + 8));
+
+    let actual = crc32fast::hash(&patch[..patch.len() - 4]);
+    if actual != patch_crc {
+        return Err(PatchError::ChecksumMismatch { what: "patch", expected: patch_crc, actual });
+    }
+    return Ok((&patch[..body_end], source_crc, target_crc));
+}
+
+fn check_crc(what: &'static str, data: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32fast::hash(data);
+    if actual != expected {
+        return Err(PatchError::ChecksumMismatch { what, expected, actual });
+    }
+    return Ok(());
+}
+
+/// Target size from a UPS/BPS header, refused past the largest ROM so a bad
+/// patch can't make us allocate gigabytes
+fn target_size(reader: &mut Reader) -> Result<usize, PatchError> {
+    let size = reader.number()?;
+    if size > MAX_ROM_SIZE {
+        return Err(PatchError::Malformed("target bigger than the largest ROM size"));
+    }
+    return Ok(size);
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (body, source_crc, target_crc) = check_footer(patch)?;
+    check_crc("source", rom, source_crc)?;
+
+    let mut reader = Reader { data: body, pos: UPS_MAGIC.len() };
+    let _source_size = reader.number()?;
+    let target_size = target_size(&mut reader)?;
+    let mut out = rom.to_vec();
+    out.resize(target_size, 0);
+
+    let mut pos: usize = 0;
+    while reader.pos < body.len() {
+        pos = pos
+            .checked_add(reader.number()?)
+            .filter(|&pos| pos <= out.len())
+            .ok_or(PatchError::Malformed("hunk past the end of target"))?;
+        loop {
+            let byte = reader.byte()?;
+            if byte == 0 {
+                pos += 1;
+                break;
+            }
+            let target = out.get_mut(pos).ok_or(PatchError::Malformed("hunk past the end of target"))?;
+            *target ^= byte;
+            pos += 1;
+        }
+    }
+
+    check_crc("target", &out, target_crc)?;
+    return Ok(out);
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (body, source_crc, target_crc) = check_footer(patch)?;
+    check_crc("source", rom, source_crc)?;
+
+    let mut reader = Reader { data: body, pos: BPS_MAGIC.len() };
+    let _source_size = reader.number()?;
+    let target_size = target_size(&mut reader)?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+
+    let mut out: Vec<u8> = Vec::new();
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+    let relative = |reader: &mut Reader, offset: &mut isize| -> Result<(), PatchError> {
+        let data = reader.number()?;
+        let delta = (data >> 1) as isize;
+        let moved = if data & 1 == 1 { offset.checked_sub(delta) } else { offset.checked_add(delta) };
+        *offset = moved.ok_or(PatchError::Malformed("offset overflow"))?;
+        return Ok(());
+    };
+
+    while reader.pos < body.len() {
+        let data = reader.number()?;
+        let len = (data >> 2) + 1;
+        if len > target_size - out.len() {
+            return Err(PatchError::Malformed("output past the target size"));
+        }
+        match data & 3 {
+            // SourceRead
+            0 => {
+                let start = out.len();
+                let bytes = start
+                    .checked_add(len)
+                    .and_then(|end| rom.get(start..end))
+                    .ok_or(PatchError::Malformed("read past the end of source"))?;
+                out.extend_from_slice(bytes);
+            }
+            // TargetRead
+            1 => out.extend_from_slice(reader.bytes(len)?),
+            // SourceCopy
+            2 => {
+                relative(&mut reader, &mut source_offset)?;
+                let end = usize::try_from(source_offset)
+                    .ok()
+                    .and_then(|start| start.checked_add(len))
+                    .filter(|&end| end <= rom.len())
+                    .ok_or(PatchError::Malformed("copy past the end of source"))?;
+                out.extend_from_slice(&rom[end - len..end]);
+                // Within the source, so it fits
+                source_offset = end as isize;
+            }
+            // TargetCopy, may overlap the bytes it is producing
+            _ => {
+                relative(&mut reader, &mut target_offset)?;
+                for _ in 0..len {
+                    let byte = *usize::try_from(target_offset)
+                        .ok()
+                        .and_then(|at| out.get(at))
+                        .ok_or(PatchError::Malformed("copy past the end of target"))?;
+                    out.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_size {
+        return Err(PatchError::Malformed("target size disagrees with patch header"));
+    }
+    check_crc("target", &out, target_crc)?;
+    return Ok(out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(mut n: usize) -> Vec<u8> {
+        let mut out = vec![];
+        loop {
+            let x = (n & 0x7F) as u8;
+            n >>= 7;
+            if n == 0 {
+                out.push(0x80 | x);
+                return out;
+            }
+            out.push(x);
+            n -= 1;
+        }
+    }
+
+    fn with_footer(mut body: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        body.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        body.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        let crc = crc32fast::hash(&body);
+        body.extend_from_slice(&crc.to_le_bytes());
+        body
+    }
+
+    #[test]
+    fn applies_ips_records_rle_and_truncation() {
+        let rom = vec![0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        patch.extend_from_slice(&[0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply(&rom, &patch).unwrap(), vec![0, 0xAA, 0xBB, 0, 0, 0, 0, 0, 0xCC, 0xCC, 0xCC]);
+
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(apply(&rom, &patch).unwrap(), vec![0, 0xAA]);
+    }
+
+    #[test]
+    fn rejects_truncated_ips() {
+        let patch = b"PATCH\x00\x00\x01\x00\x04\xAA".to_vec();
+        assert_eq!(apply(&[0; 4], &patch), Err(PatchError::Malformed("unexpected end of patch")));
+    }
+
+    #[test]
+    fn applies_ups_and_checks_crcs() {
+        let rom = vec![1, 2, 3, 4];
+        let target = vec![1, 7, 3, 4, 9];
+        let mut body = b"UPS1".to_vec();
+        body.extend(number(4));
+        body.extend(number(5));
+        body.extend(number(1));
+        body.extend_from_slice(&[2 ^ 7, 0x00]);
+        body.extend(number(1));
+        body.extend_from_slice(&[9, 0x00]);
+        let patch = with_footer(body, &rom, &target);
+
+        assert_eq!(apply(&rom, &patch).unwrap(), target);
+        assert!(matches!(
+            apply(&[1, 2, 3, 5], &patch),
+            Err(PatchError::ChecksumMismatch { what: "source", .. })
+        ));
+
+        let mut corrupt = patch.clone();
+        corrupt[6] ^= 1;
+        assert!(matches!(apply(&rom, &corrupt), Err(PatchError::ChecksumMismatch { what: "patch", .. })));
+    }
+
+    #[test]
+    fn applies_every_bps_action() {
+        let rom = vec![10, 11, 12, 13];
+        let target = vec![10, 11, 42, 12, 13, 42, 12];
+        let mut body = b"BPS1".to_vec();
+        body.extend(number(4));
+        body.extend(number(7));
+        body.extend(number(0));
+        body.extend(number(1 << 2)); // SourceRead 2
+        body.extend(number(1)); // TargetRead 1
+        body.push(42);
+        body.extend(number((1 << 2) | 2)); // SourceCopy 2 from +2
+        body.extend(number(2 << 1));
+        body.extend(number((1 << 2) | 3)); // TargetCopy 2 from +2
+        body.extend(number(2 << 1));
+        let patch = with_footer(body, &rom, &target);
+
+        assert_eq!(apply(&rom, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn rejects_overflowing_bps_offsets() {
+        let rom = vec![10, 11, 12, 13];
+        let bps = |actions: &[Vec<u8>]| {
+            let mut body = b"BPS1".to_vec();
+            body.extend(number(4));
+            body.extend(number(8));
+            body.extend(number(0));
+            for action in actions {
+                body.extend(action);
+            }
+            with_footer(body, &rom, &rom)
+        };
+        let copy = |len: usize| number(((len - 1) << 2) | 2);
+
+        // Copying one byte leaves the source offset at 1, then jumps isize::MAX ahead
+        let patch = bps(&[copy(1), number(0), copy(1), number(usize::MAX - 1)]);
+        assert_eq!(apply(&rom, &patch), Err(PatchError::Malformed("offset overflow")));
+
+        let patch = bps(&[copy(4), number(2 << 1)]);
+        assert_eq!(apply(&rom, &patch), Err(PatchError::Malformed("copy past the end of source")));
+
+        let patch = bps(&[number(4 << 2)]);
+        assert_eq!(apply(&rom, &patch), Err(PatchError::Malformed("read past the end of source")));
+    }
+
+    #[test]
+    fn bounds_the_patched_size() {
+        let rom = vec![1, 2, 3, 4];
+        let mut ips = b"PATCH".to_vec();
+        ips.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00]);
+        ips.extend_from_slice(b"EOF");
+        assert_eq!(apply(&rom, &ips), Err(PatchError::Malformed("record past the largest ROM size")));
+
+        let mut body = b"UPS1".to_vec();
+        body.extend(number(4));
+        body.extend(number(MAX_ROM_SIZE + 1));
+        let patch = with_footer(body, &rom, &rom);
+        assert_eq!(apply(&rom, &patch), Err(PatchError::Malformed("target bigger than the largest ROM size")));
+
+        let mut body = b"UPS1".to_vec();
+        body.extend(number(4));
+        body.extend(number(4));
+        body.extend(number(usize::MAX));
+        let patch = with_footer(body, &rom, &rom);
+        assert_eq!(apply(&rom, &patch), Err(PatchError::Malformed("hunk past the end of target")));
+
+        // TargetCopy repeating the first byte past the declared 4 bytes
+        let mut body = b"BPS1".to_vec();
+        body.extend(number(4));
+        body.extend(number(4));
+        body.extend(number(0));
+        body.extend(number(0)); // SourceRead 1
+        body.extend(number((0x10000 << 2) | 3));
+        body.extend(number(0));
+        let patch = with_footer(body, &rom, &rom);
+        assert_eq!(apply(&rom, &patch), Err(PatchError::Malformed("output past the target size")));
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert_eq!(apply(&[0; 4], b"NOPE"), Err(PatchError::UnknownFormat));
+    }
+}
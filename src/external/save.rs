@@ -0,0 +1,103 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::mbc::MemoryBankController;
+
+/// Extensions dropped before naming the save, so a compressed copy of a ROM
+/// shares the save of the raw one
+const ARCHIVE_EXTENSIONS: [&str;2] = ["gz", "zip"];
+
+/// Battery backed cartridge RAM stored next to the ROM as a raw dump,
+/// followed by the RTC footer for cartridges with a clock
+pub struct SaveFile {
+  pub path: PathBuf,
+  last_written: Vec<u8>,
+}
+
+impl SaveFile {
+  pub fn for_rom(rom_path: &str) -> Self {
+    let mut path = Path::new(rom_path).to_path_buf();
+    let archived = path
+      .extension()
+      .map_or(false, |ext| ARCHIVE_EXTENSIONS.iter().any(|archive| ext.eq_ignore_ascii_case(archive)));
+    if archived {
+      path.set_extension("");
+    }
+    SaveFile {
+      path: path.with_extension("sav"),
+      last_written: vec!(),
+    }
+  }
+
+  /// Restores RAM and clock state, returns false when there is no save yet
+  pub fn load(&mut self, mbc: &mut dyn MemoryBankController) -> io::Result<bool> {
+    let data = match fs::read(&self.path) {
+      Ok(data) => data,
+      Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+      Err(e) => return Err(e),
+    };
+    let ram_len = std::cmp::min(mbc.ram_data().len(), data.len());
+    mbc.load_ram_data(&data[..ram_len]);
+    if data.len() > ram_len {
+      mbc.load_rtc_data(&data[ram_len..])
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))?;
+    }
+    self.last_written = data[..ram_len].to_vec();
+    Ok(true)
+  }
+
+  /// Writes the save when RAM changed since the last write, or always when
+  /// `force` is set so the clock footer is current on exit
+  pub fn flush(&mut self, mbc: &dyn MemoryBankController, force: bool) -> io::Result<bool> {
+    let ram = mbc.ram_data();
+    if !force && ram == self.last_written.as_slice() {
+      return Ok(false);
+    }
+    let mut data = ram.to_vec();
+    if let Some(rtc) = mbc.rtc_data() {
+      data.extend(rtc);
+    }
+    fs::write(&self.path, &data)?;
+    self.last_written = ram.to_vec();
+    Ok(true)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mbc::mbc3::{Mbc3, RTC_FOOTER_SIZE};
+    use crate::mbc::ROM_BANK_SIZE;
+
+    #[test]
+    fn archived_roms_share_the_raw_rom_save() {
+      assert_eq!(SaveFile::for_rom("roms/game.gb").path, Path::new("roms/game.sav"));
+      assert_eq!(SaveFile::for_rom("roms/game.gb.gz").path, Path::new("roms/game.sav"));
+      assert_eq!(SaveFile::for_rom("roms/game.GZ").path, Path::new("roms/game.sav"));
+      assert_eq!(SaveFile::for_rom("roms/game.zip").path, Path::new("roms/game.sav"));
+    }
+
+    #[test]
+    fn round_trips_ram_and_rtc_footer() {
+      let dir = std::env::temp_dir().join(format!("gb-save-test-{}", std::process::id()));
+      fs::create_dir_all(&dir).unwrap();
+      let rom_path = dir.join("game.gb");
+      let mut save = SaveFile::for_rom(rom_path.to_str().unwrap());
+      assert_eq!(save.path, dir.join("game.sav"));
+
+      let mut mbc = Mbc3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000, true);
+      assert!(!save.load(&mut mbc).unwrap());
+      mbc.write_rom(0x0000, 0x0A);
+      mbc.write_ram(0x0010, 0x5A);
+      assert!(save.flush(&mbc, false).unwrap());
+      assert!(!save.flush(&mbc, false).unwrap());
+      assert_eq!(fs::read(&save.path).unwrap().len(), 0x2000 + RTC_FOOTER_SIZE);
+
+      let mut restored = Mbc3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000, true);
+      assert!(SaveFile::for_rom(rom_path.to_str().unwrap()).load(&mut restored).unwrap());
+      restored.write_rom(0x0000, 0x0A);
+      assert_eq!(restored.read_ram(0x0010), 0x5A);
+
+      fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,23 @@
-use crate::cpu::cpu::CPU;
+use crate::cpu::cpu::{CpuState, CPU};
 use crate::debug::logger::{LogEvents, LoggableComponent, LoggerClient};
 use crate::debug::{debugger::Debuggable, gb_debugger::DebuggerState};
 use crate::debug::{input::DebuggerInput, logger::LogMessage, ui::Terminal};
 use crate::external::boot_rom_loader;
 use crate::external::cartridge::Cartridge;
+use crate::external::error::LoadError;
+use crate::external::save::SaveFile;
 use crate::gb_config::gb_config;
 use crate::mmu::VirtualMemory;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// T-cycles in a full frame (154 lines of 456 dots)
+pub const CYCLES_PER_FRAME: u64 = 70224;
+/// Real time a DMG frame takes at 4.194304 MHz (~59.73 fps)
+const FRAME_DURATION: Duration = Duration::from_nanos(16_742_706);
+/// Frames between battery save flushes (~5 seconds)
+const SAVE_FLUSH_FRAMES: u64 = 300;
 
 pub struct Gameboy {
     pub cpu: CPU,
@@ -17,6 +29,10 @@ pub struct Gameboy {
     pub state: DebuggerState,
 
     logger_client: LoggerClient,
+    save: Option<SaveFile>,
+    rumble: bool,
+    rumble_callback: Option<Box<dyn FnMut(bool)>>,
+    stop_requested: Arc<AtomicBool>,
 }
 
 pub trait ExternalHook {
@@ -29,54 +45,97 @@ impl Gameboy {
     pub fn new(cfg: gb_config, logger_client: LoggerClient) -> Self {
         Gameboy {
             cpu: CPU::new(false),
-            mmu: VirtualMemory::new(),
+            mmu: VirtualMemory::with_renderer(cfg.renderer),
             cartridge: None,
             terminal: Terminal::new(8),
             input: DebuggerInput::new(),
             state: DebuggerState::new(),
             cfg,
             logger_client,
+            save: None,
+            rumble: false,
+            rumble_callback: None,
+            stop_requested: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    pub fn load_cartridge(&mut self, cartridge: Cartridge) {
+    /// Registers a callback fired whenever the cartridge motor turns on or off
+    pub fn on_rumble<F: FnMut(bool) + 'static>(&mut self, callback: F) {
+        self.rumble_callback = Some(Box::new(callback));
+    }
+
+    /// Flag that makes `start` return after the current instruction, safe to
+    /// set from another thread or a signal handler
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        return self.stop_requested.clone();
+    }
+
+    pub fn load_cartridge(&mut self, cartridge: Cartridge) -> Result<(), LoadError> {
         self.logger_client
             .send((LogEvents::Initializing, String::from("Initializing ROM")));
-        match self.mmu.load_rom(cartridge.content.as_slice()) {
-            Ok(size) => self.logger_client.send((
-                LogEvents::Initializing,
-                format!("{} bytes loaded into memory", size),
-            )),
-            Err(why) => panic!("Failed to load ROM {}", why), // @TODO HANDLE PANICS
+        if let Some(patch) = &cartridge.patch {
+            self.logger_client
+                .send((LogEvents::Initializing, format!("Applied patch {}", patch)));
+        }
+        let report = cartridge.verify();
+        for problem in report.problems() {
+            self.logger_client
+                .send((LogEvents::Initializing, format!("[WARNING] {}", problem)));
+        }
+        if self.cfg.strict_header {
+            report.strict()?;
         }
 
+        let size = self.mmu.load_cartridge(&cartridge)?;
+        self.logger_client.send((
+            LogEvents::Initializing,
+            format!("{} bytes loaded into memory", size),
+        ));
+
+        self.load_save(&cartridge);
+
+        let header_checksum = cartridge.header_checksum();
         self.cartridge = Some(cartridge);
         if self.cfg.boot_rom_enabled {
-            let boot_rom = boot_rom_loader::load_boot_rom(&self.cfg.boot_rom_path);
-            match self.mmu.load_rom(boot_rom.as_slice()) {
-                Ok(size) => self.logger_client.send((
-                    LogEvents::Initializing,
-                    format!("{} bytes loaded into boot rom area", size),
-                )),
-                Err(why) => panic!("Failed to load boot rom {}", why),
-            }
+            let boot_rom = boot_rom_loader::load_boot_rom(&self.cfg.boot_rom_path)?;
+            let size = self.mmu.load_boot_rom(boot_rom.as_slice())?;
+            self.logger_client.send((
+                LogEvents::Initializing,
+                format!("{} bytes loaded into boot rom area", size),
+            ));
         } else {
-            self.cpu.reg.pc = 0x100;
+            self.cpu.reg = self.cfg.model.post_boot_registers(header_checksum);
+            self.mmu.load_io_state(&self.cfg.model.post_boot_io());
+            self.logger_client.send((
+                LogEvents::Initializing,
+                format!("Skipping boot rom with {:?} post-boot state", self.cfg.model),
+            ));
         }
+        Ok(())
     }
 
-    pub fn start(&mut self) {
+    /// Runs until the configured frame limit, forever without one, or until a
+    /// stop is requested or the CPU locks up. Flushes the save file before returning the number of
+    /// frames run.
+    pub fn start(&mut self) -> u64 {
         self.logger_client.send((
             LogEvents::Initializing,
             String::from("Starting GB main loop"),
         ));
-        if self.cfg.debug_mode {
+        let debugging = self.cfg.debug_mode && !self.cfg.headless;
+        if debugging {
             self.state.breakpoints.push(self.cfg.initial_breakpoint);
             self.on_started();
         }
 
-        loop {
-            if self.cfg.debug_mode {
+        let mut frame_start = Instant::now();
+        let mut next_frame = self.cpu.t_cycles() + CYCLES_PER_FRAME;
+        let mut frames: u64 = 0;
+
+        while self.cfg.frame_limit.map_or(true, |limit| frames < limit)
+            && !self.stop_requested.load(Ordering::Relaxed)
+        {
+            if debugging {
                 if self.should_stop(self.cpu.reg.pc as u16) {
                     self.on_breakpoint(self.cpu.reg.pc as u16);
                     if self.state.log_next {
@@ -89,18 +148,251 @@ impl Gameboy {
                 }
             }
 
-            let mut messages: Vec<LogMessage> = vec![];
-            self.cpu.tick(&mut self.mmu);
-            messages.extend(self.cpu.dump_log_messages());
-            messages.extend(self.mmu.dump_log_messages());
+            self.step();
+            if self.cpu.state == CpuState::Locked {
+                let addr = self.cpu.reg.pc.wrapping_sub(1) & 0xFFFF;
+                self.logger_client.send((
+                    LogEvents::Lockup,
+                    format!("CPU locked up on illegal opcode {:#04x} @ {:#06x}, stopping", self.mmu.peek(addr), addr),
+                ));
+                break;
+            }
 
-            for m in messages {
-                self.logger_client.send(m);
+            if self.cpu.t_cycles() >= next_frame {
+                next_frame += CYCLES_PER_FRAME;
+                frames += 1;
+                if frames % SAVE_FLUSH_FRAMES == 0 {
+                    self.flush_save(false);
+                }
+                let elapsed = frame_start.elapsed();
+                if !self.cfg.headless && elapsed < FRAME_DURATION {
+                    std::thread::sleep(FRAME_DURATION - elapsed);
+                }
+                frame_start = Instant::now();
             }
 
             // for s in self.cpu.reg.print_registers() {
             //   self.logger_client.send((LogEvents::Register, format!("\t[REG] {}", s)));
             // }
         }
+        self.logger_client.send((
+            LogEvents::Initializing,
+            format!("Stopped after {} frames", frames),
+        ));
+        self.flush_save(true);
+        return frames;
+    }
+
+    fn load_save(&mut self, cartridge: &Cartridge) {
+        let path = match (&cartridge.path, cartridge.cartridge_type().has_battery()) {
+            (Some(path), true) => path,
+            _ => return,
+        };
+        let mut save = SaveFile::for_rom(path);
+        match save.load(self.mmu.mbc.as_mut()) {
+            Ok(true) => self.logger_client.send((
+                LogEvents::Save,
+                format!("Loaded save file {}", save.path.display()),
+            )),
+            Ok(false) => (),
+            Err(why) => self.logger_client.send((
+                LogEvents::Save,
+                format!("Failed to load save file {}: {}", save.path.display(), why),
+            )),
+        }
+        self.save = Some(save);
+    }
+
+    /// Writes battery backed RAM to disk. Without `force` nothing is written
+    /// unless RAM changed since the last flush.
+    pub fn flush_save(&mut self, force: bool) {
+        if let Some(save) = self.save.as_mut() {
+            match save.flush(self.mmu.mbc.as_ref(), force) {
+                Ok(true) => self.logger_client.send((
+                    LogEvents::Save,
+                    format!("Saved {}", save.path.display()),
+                )),
+                Ok(false) => (),
+                Err(why) => self.logger_client.send((
+                    LogEvents::Save,
+                    format!("Failed to write save file {}: {}", save.path.display(), why),
+                )),
+            }
+        }
+    }
+
+    /// Runs one instruction and advances the rest of the system accordingly
+    pub fn step(&mut self) -> u8 {
+        let mut messages: Vec<LogMessage> = vec![];
+        let cycles = self.cpu.tick(&mut self.mmu);
+        self.mmu.tick(cycles);
+        messages.extend(self.cpu.dump_log_messages());
+        messages.extend(self.mmu.dump_log_messages());
+
+        let rumble = self.mmu.mbc.rumble();
+        if rumble != self.rumble {
+            self.rumble = rumble;
+            messages.push((LogEvents::Rumble, format!("[RUMBLE] Motor {}", if rumble { "on" } else { "off" })));
+            if let Some(callback) = self.rumble_callback.as_mut() {
+                callback(rumble);
+            }
+        }
+
+        for m in messages {
+            self.logger_client.send(m);
+        }
+        return cycles;
+    }
+
+    /// Shades (0 lightest to 3 darkest) of the last drawn 160x144 frame, row by row
+    pub fn framebuffer(&self) -> &[u8] {
+        return &self.mmu.ppu.framebuffer;
+    }
+
+    /// Whether the PPU finished a frame since the last call
+    pub fn take_frame(&mut self) -> bool {
+        return self.mmu.ppu.take_frame();
+    }
+}
+
+impl Drop for Gameboy {
+    /// Catches exits that never got back from `start`, e.g. a panic
+    fn drop(&mut self) {
+        self.flush_save(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    fn config(strict_header: bool) -> gb_config {
+        gb_config { strict_header, ..gb_config::default() }
+    }
+
+    #[test]
+    fn notifies_rumble_changes() {
+        let (chin, _chout) = mpsc::channel();
+        let mut gb = Gameboy::new(config(false), LoggerClient::new(chin));
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x1C; // MBC5+RUMBLE
+        // LD A,0x08; LD (0x4000),A; XOR A; LD (0x4000),A
+        rom[0x100..0x108].copy_from_slice(&[0x3E, 0x08, 0xEA, 0x00, 0x40, 0xAF, 0xEA, 0x00]);
+        rom[0x108] = 0x40;
+        gb.load_cartridge(Cartridge::new(&rom).unwrap()).unwrap();
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let observed = events.clone();
+        gb.on_rumble(move |on| observed.borrow_mut().push(on));
+        for _ in 0..4 {
+            gb.step();
+        }
+        assert_eq!(*events.borrow(), vec![true, false]);
+    }
+
+    #[test]
+    fn rejects_bad_header_in_strict_mode() {
+        let (chin, _chout) = mpsc::channel();
+        let mut gb = Gameboy::new(config(true), LoggerClient::new(chin));
+
+        let result = gb.load_cartridge(Cartridge::new(&vec![0; 0x8000]).unwrap());
+        assert!(matches!(result, Err(LoadError::InvalidHeader(_))));
+        assert!(gb.cartridge.is_none());
+    }
+
+    #[test]
+    fn size_mismatch_only_fails_in_strict_mode() {
+        let mut rom = Cartridge::from_file("./res/test/01-special.gb").unwrap().content;
+        rom.resize(0x10000, 0xFF); // Overdump
+
+        let (chin, chout) = mpsc::channel();
+        let mut gb = Gameboy::new(config(false), LoggerClient::new(chin));
+        gb.load_cartridge(Cartridge::new(&rom).unwrap()).unwrap();
+        assert!(chout.try_iter().any(|(_, m)| m.starts_with("[WARNING] ROM size mismatch")));
+
+        let (chin, _chout) = mpsc::channel();
+        let mut gb = Gameboy::new(config(true), LoggerClient::new(chin));
+        let result = gb.load_cartridge(Cartridge::new(&rom).unwrap());
+        assert!(matches!(result, Err(LoadError::SizeMismatch { header: 0x8000, file: 0x10000 })));
+    }
+
+    #[test]
+    fn reports_rom_only_images_too_big_to_map() {
+        let (chin, _chout) = mpsc::channel();
+        let mut gb = Gameboy::new(config(false), LoggerClient::new(chin));
+
+        let result = gb.load_cartridge(Cartridge::new(&vec![0; 0x10000]).unwrap());
+        assert!(matches!(result, Err(LoadError::RomTooLarge { size: 0x10000, max: 0x8000 })));
+    }
+
+    #[test]
+    fn reports_unsupported_mapper() {
+        let (chin, _chout) = mpsc::channel();
+        let mut gb = Gameboy::new(config(false), LoggerClient::new(chin));
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x20; // MBC6
+        let result = gb.load_cartridge(Cartridge::new(&rom).unwrap());
+        assert!(matches!(result, Err(LoadError::UnsupportedMapper(0x20))));
+    }
+
+    #[test]
+    fn headless_run_stops_at_frame_limit() {
+        let cfg = gb_config { headless: true, debug_mode: true, frame_limit: Some(3), ..gb_config::default() };
+        let (chin, _chout) = mpsc::channel();
+        let mut gb = Gameboy::new(cfg, LoggerClient::new(chin));
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // JR -2
+        gb.load_cartridge(Cartridge::new(&rom).unwrap()).unwrap();
+
+        assert_eq!(gb.start(), 3);
+        assert!(gb.cpu.t_cycles() >= 3 * CYCLES_PER_FRAME);
+        assert!(gb.take_frame());
+        assert_eq!(gb.framebuffer().len(), 160 * 144);
+    }
+
+    #[test]
+    fn illegal_opcode_stops_the_run() {
+        let cfg = gb_config { headless: true, ..gb_config::default() };
+        let (chin, chout) = mpsc::channel();
+        let mut gb = Gameboy::new(cfg, LoggerClient::new(chin));
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x101] = 0xDD;
+        gb.load_cartridge(Cartridge::new(&rom).unwrap()).unwrap();
+
+        assert_eq!(gb.start(), 0);
+        assert!(chout.try_iter().any(|(event, m)| {
+            matches!(event, LogEvents::Lockup) && m.contains("0xdd @ 0x0101")
+        }));
+    }
+
+    #[test]
+    fn stop_request_ends_the_run_and_flushes_the_save() {
+        let cfg = gb_config { headless: true, ..gb_config::default() };
+        let (chin, chout) = mpsc::channel();
+        let mut gb = Gameboy::new(cfg, LoggerClient::new(chin));
+
+        let dir = std::env::temp_dir().join(format!("gb-stop-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb");
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+        rom[0x149] = 0x02; // 8 KiB
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // JR -2
+        let mut cartridge = Cartridge::new(&rom).unwrap();
+        cartridge.path = Some(rom_path.to_str().unwrap().to_string());
+        gb.load_cartridge(cartridge).unwrap();
+
+        gb.stop_handle().store(true, Ordering::Relaxed);
+        assert_eq!(gb.start(), 0);
+        assert!(dir.join("game.sav").exists());
+        assert!(chout.try_iter().any(|(_, m)| m.starts_with("Saved")));
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -1,23 +1,186 @@
+use crate::debug::logger::LogEvents;
+use crate::model::Model;
+use crate::ppu::core::Renderer;
+
+/// Which debug log categories reach the output
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogFlags {
+  pub tick: bool,
+  pub memory_save: bool,
+  pub memory_fetch: bool,
+  pub vram_save: bool,
+  pub decoding: bool,
+  pub snapshot: bool,
+  pub register: bool,
+  pub interrupt: bool,
+  pub rumble: bool,
+}
+
+impl LogFlags {
+  pub fn all(enabled: bool) -> Self {
+    LogFlags {
+      tick: enabled,
+      memory_save: enabled,
+      memory_fetch: enabled,
+      vram_save: enabled,
+      decoding: enabled,
+      snapshot: enabled,
+      register: enabled,
+      interrupt: enabled,
+      rumble: enabled,
+    }
+  }
+
+  /// Parses a comma separated list of category names, or "all"
+  pub fn from_list(list: &str) -> Result<Self, String> {
+    let mut flags = LogFlags::all(false);
+    for name in list.split(',').map(|n| n.trim().replace('-', "_")) {
+      match name.as_str() {
+        "all" => flags = LogFlags::all(true),
+        "" => (),
+        _ => *flags.flag_mut(&name).ok_or_else(|| format!("Unknown log flag {}", name))? = true,
+      }
+    }
+    return Ok(flags);
+  }
+
+  fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+    return match name {
+      "tick" => Some(&mut self.tick),
+      "memory_save" => Some(&mut self.memory_save),
+      "memory_fetch" => Some(&mut self.memory_fetch),
+      "vram_save" => Some(&mut self.vram_save),
+      "decoding" => Some(&mut self.decoding),
+      "snapshot" => Some(&mut self.snapshot),
+      "register" => Some(&mut self.register),
+      "interrupt" => Some(&mut self.interrupt),
+      "rumble" => Some(&mut self.rumble),
+      _ => None,
+    }
+  }
+
+  /// Whether a debug event of this kind should be printed. Events outside the
+  /// debug categories are always let through.
+  pub fn allows(&self, event: LogEvents) -> bool {
+    return match event {
+      LogEvents::Tick => self.tick,
+      LogEvents::MemorySave => self.memory_save,
+      LogEvents::MemoryFetch => self.memory_fetch,
+      LogEvents::VramSave => self.vram_save,
+      LogEvents::Decoding => self.decoding,
+      LogEvents::Snapshot => self.snapshot,
+      LogEvents::Register => self.register,
+      LogEvents::Interrupt => self.interrupt,
+      LogEvents::Rumble => self.rumble,
+      _ => true,
+    }
+  }
+}
 
 #[derive(Clone)]
 pub struct gb_config {
   pub boot_rom_enabled: bool,
   pub boot_rom_path: String,
   pub debug_mode: bool,
-  pub initial_breakpoint: u16
+  pub initial_breakpoint: u16,
+  pub model: Model,
+  pub renderer: Renderer,
+  /// Refuse cartridges failing header verification instead of warning
+  pub strict_header: bool,
+  /// Run unthrottled with no interactive debugger
+  pub headless: bool,
+  /// Stop after this many frames
+  pub frame_limit: Option<u64>,
+  /// Print debug log categories without waiting for the debugger to turn them on
+  pub logger_enabled: bool,
+  pub log_flags: LogFlags,
+}
+
+impl Default for gb_config {
+  fn default() -> Self {
+    gb_config {
+      boot_rom_enabled: false,
+      boot_rom_path: String::new(),
+      debug_mode: false,
+      initial_breakpoint: 0,
+      model: Model::DMG,
+      renderer: Renderer::Scanline,
+      strict_header: false,
+      headless: false,
+      frame_limit: None,
+      logger_enabled: false,
+      log_flags: LogFlags::all(true),
+    }
+  }
 }
 
 impl gb_config {
-  pub fn new(path: &str) -> Self {
+  /// Reads a settings file. Missing keys keep their default value.
+  pub fn new(path: &str) -> Result<Self, String> {
     let mut settings = config::Config::default();
-    return match settings.merge(config::File::with_name(path)) {
-      Ok(c) => gb_config {
-          boot_rom_enabled: c.get_bool("boot_rom_enabled").unwrap(),
-          boot_rom_path: c.get_str("boot_rom_path").unwrap(),
-          debug_mode: c.get_bool("debug_enabled").unwrap(),
-          initial_breakpoint: c.get_int("debug_initial_breakpoint").unwrap_or(0x00) as u16,
-        },
-      Err(why) => panic!("Failed to load config file {}: {}", path, why)
-    }
+    let c = match settings.merge(config::File::with_name(path)) {
+      Ok(c) => c,
+      Err(why) => return Err(format!("Failed to load config file {}: {}", path, why)),
+    };
+
+    let defaults = gb_config::default();
+    let flag = |key: &str, default: bool| c.get_bool(key).unwrap_or(default);
+    return Ok(gb_config {
+      boot_rom_enabled: flag("boot_rom_enabled", defaults.boot_rom_enabled),
+      boot_rom_path: c.get_str("boot_rom_path").unwrap_or(defaults.boot_rom_path),
+      debug_mode: flag("debug_enabled", defaults.debug_mode),
+      initial_breakpoint: c.get_int("debug_initial_breakpoint").unwrap_or(0x00) as u16,
+      model: match c.get_str("model") {
+        Ok(name) => Model::from_name(&name).ok_or_else(|| format!("Unknown model {}", name))?,
+        Err(_) => defaults.model,
+      },
+      renderer: match c.get_str("renderer") {
+        Ok(name) => Renderer::from_name(&name).ok_or_else(|| format!("Unknown renderer {}", name))?,
+        Err(_) => defaults.renderer,
+      },
+      strict_header: flag("strict_header", defaults.strict_header),
+      headless: flag("headless", defaults.headless),
+      frame_limit: c.get_int("frame_limit").ok().map(|n| n as u64),
+      logger_enabled: flag("logger_enabled", defaults.logger_enabled),
+      log_flags: LogFlags {
+        tick: flag("logger_flag_tick", true),
+        memory_save: flag("logger_flag_memory_save", true),
+        memory_fetch: flag("logger_memory_fetch", true),
+        vram_save: flag("logger_vram_save", true),
+        decoding: flag("logger_decoding", true),
+        snapshot: flag("logger_snapshot", true),
+        register: flag("logger_register", true),
+        interrupt: flag("logger_interrupt", true),
+        rumble: flag("logger_rumble", true),
+      },
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_log_flag_lists() {
+    let flags = LogFlags::from_list("tick, memory-fetch").unwrap();
+    assert!(flags.allows(LogEvents::Tick));
+    assert!(flags.allows(LogEvents::MemoryFetch));
+    assert!(!flags.allows(LogEvents::MemorySave));
+    assert!(flags.allows(LogEvents::Save));
+
+    assert_eq!(LogFlags::from_list("all").unwrap(), LogFlags::all(true));
+    assert!(LogFlags::from_list("tick,bogus").is_err());
+  }
+
+  #[test]
+  fn reads_repository_settings() {
+    let cfg = gb_config::new("Settings").unwrap();
+    assert_eq!(cfg.model, Model::DMG);
+    assert_eq!(cfg.renderer, Renderer::Scanline);
+    assert!(!cfg.headless);
+    assert_eq!(cfg.frame_limit, None);
+    assert_eq!(cfg.log_flags, LogFlags::all(true));
+    assert!(gb_config::new("missing-settings").is_err());
   }
 }
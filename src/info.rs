@@ -0,0 +1,117 @@
+use serde_json::{json, Value};
+
+use crate::external::cartridge::{Cartridge, CGB_Flag, Check, SGB_Flag};
+use crate::mbc;
+
+/// Human readable report printed by the `info` command
+pub fn describe(cartridge: &Cartridge) -> String {
+  let report = cartridge.verify();
+  let mut lines = vec![];
+  if let Some(path) = &cartridge.path {
+    lines.push(format!("File:            {}", path));
+  }
+  if let Some(patch) = &cartridge.patch {
+    lines.push(format!("Patch:           {}", patch));
+  }
+  lines.push(cartridge.to_string());
+  lines.push(format!("Entry point:     {}", entry_point(cartridge)));
+  lines.push(format!("Mapper support:  {}", if mapper_supported(cartridge) { "yes" } else { "no" }));
+  lines.push(format!("Nintendo logo:   {}", if report.logo.is_ok() { "ok" } else { "mismatch" }));
+  lines.push(format!("Header check:    {}", check_summary(&report.header_checksum)));
+  lines.push(format!("Global check:    {}", check_summary(&report.global_checksum)));
+  lines.push(format!("Size check:      {}", check_summary(&report.rom_size)));
+  return lines.join("\n");
+}
+
+/// Same fields as `describe`, for cataloguing ROM sets from scripts
+pub fn to_json(cartridge: &Cartridge) -> Value {
+  let report = cartridge.verify();
+  return json!({
+    "path": cartridge.path,
+    "patch": cartridge.patch,
+    "title": cartridge.title(),
+    "manufacturer_code": cartridge.manufacturer_code(),
+    "licensee_code": cartridge.licensee_code(),
+    "licensee": cartridge.licensee(),
+    "entry_point": entry_point(cartridge),
+    "cartridge_type": format!("{:?}", cartridge.cartridge_type()),
+    "mapper_supported": mapper_supported(cartridge),
+    "battery": cartridge.cartridge_type().has_battery(),
+    "rom_size": cartridge.rom_size_bytes(),
+    "rom_banks": cartridge.rom_size().banks(),
+    "image_size": cartridge.content.len(),
+    "rom_size_valid": report.rom_size.is_ok(),
+    "ram_size": cartridge.ram_size_bytes(),
+    "cgb": cgb_name(cartridge.cgb_flag()),
+    "sgb": *cartridge.sgb_flag() == SGB_Flag::Support,
+    "region": cartridge.region().to_string(),
+    "version": cartridge.version(),
+    "logo_valid": report.logo.is_ok(),
+    "header_checksum": {
+      "stored": cartridge.header_checksum(),
+      "computed": cartridge.compute_header_checksum(),
+      "valid": report.header_checksum.is_ok(),
+    },
+    "global_checksum": {
+      "stored": cartridge.global_checksum(),
+      "computed": cartridge.compute_global_checksum(),
+      "valid": report.global_checksum.is_ok(),
+    },
+    "bootable": report.is_bootable(),
+  });
+}
+
+/// Code at 0x100-0x103 as hex bytes, usually NOP; JP to the real start
+fn entry_point(cartridge: &Cartridge) -> String {
+  return cartridge.entry_point().iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+}
+
+fn mapper_supported(cartridge: &Cartridge) -> bool {
+  return mbc::from_cartridge(cartridge).is_ok();
+}
+
+fn cgb_name(flag: &CGB_Flag) -> &'static str {
+  return match flag {
+    CGB_Flag::RetroCompatible => "enhanced",
+    CGB_Flag::NonRetroCompatible => "required",
+    CGB_Flag::Unkown => "none",
+  }
+}
+
+fn check_summary<T: std::fmt::LowerHex>(check: &Check<T>) -> String {
+  return match check {
+    Check::Ok => String::from("ok"),
+    Check::Mismatch { expected, actual } =>
+      format!("mismatch (computed {:#x}, header says {:#x})", expected, actual),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reports_intr_01_as_json() {
+    let cartridge = Cartridge::from_file("./res/test/01-special.gb").unwrap();
+    let info = to_json(&cartridge);
+    assert_eq!(info["cartridge_type"], "MBC1");
+    assert_eq!(info["mapper_supported"], true);
+    assert_eq!(info["entry_point"], "00 c3 13 02");
+    assert_eq!(info["rom_size"], 0x8000);
+    assert_eq!(info["rom_size_valid"], true);
+    assert_eq!(info["cgb"], "enhanced");
+    assert_eq!(info["sgb"], false);
+    assert_eq!(info["header_checksum"]["stored"], 0x66);
+    assert_eq!(info["header_checksum"]["valid"], true);
+    assert_eq!(info["bootable"], true);
+  }
+
+  #[test]
+  fn describes_checksum_mismatches() {
+    let mut rom = Cartridge::from_file("./res/test/01-special.gb").unwrap().content;
+    rom[0x14D] = 0x00;
+    let text = describe(&Cartridge::new(&rom).unwrap());
+    assert!(text.contains("Header check:    mismatch (computed 0x66, header says 0x0)"));
+    assert!(text.contains("Mapper support:  yes"));
+  }
+}
@@ -1,38 +1,81 @@
 mod external;
 mod cpu;
 mod mmu;
+mod dma;
+mod mbc;
+mod ppu;
 mod reg;
 mod gameboy;
 mod gb_config;
+mod model;
+mod info;
+mod cli;
 mod debug;
 
-use std::env;
-use external::cartridge::{self, Cartridge};
+use clap::ArgMatches;
+use debug::logger::LogEvents;
+use external::cartridge::{self, LoadOptions};
+use std::sync::atomic::Ordering;
+
+fn exit_with(message: String) -> ! {
+  eprintln!("{}", message);
+  std::process::exit(1);
+}
+
+fn run_info(matches: &ArgMatches) {
+  let path = matches.value_of("rom").unwrap();
+  let cartridge = match cartridge::Cartridge::load(path, &LoadOptions::default()) {
+    Ok(c) => c,
+    Err(why) => exit_with(format!("Failed to load ROM: {}", why)),
+  };
+  if matches.is_present("json") {
+    println!("{}", info::to_json(&cartridge));
+  } else {
+    println!("{}", info::describe(&cartridge));
+  }
+}
 
 fn main() {
-  let args: Vec<String> = env::args().collect();
-  let cartridge_filename = &args[1];
+  let matches = cli::app().get_matches();
+  if let Some(info_matches) = matches.subcommand_matches("info") {
+    run_info(info_matches);
+    return;
+  }
 
-  let file_path: String = String::from(cartridge_filename);
-  let cartridge = cartridge::Cartridge::from_file(&file_path);
+  let file_path = matches.value_of("rom").unwrap();
+  let cartridge = match cartridge::Cartridge::load(file_path, &cli::load_options(&matches)) {
+    Ok(c) => c,
+    Err(why) => exit_with(format!("Failed to load ROM: {}", why)),
+  };
 
-  let cfg = gb_config::gb_config::new(&String::from("Settings"));
+  let cfg = cli::config(&matches).unwrap_or_else(|why| exit_with(why));
 
   let mut logger = debug::logger::Logger::new(cfg.clone());
   let client = logger.make_client();
+  let exit_client = logger.make_client();
 
   let mut gb = gameboy::Gameboy::new(cfg, client);
+  // Ctrl-C stops the main loop so the save gets flushed before exiting
+  let stop = gb.stop_handle();
+  if let Err(why) = ctrlc::set_handler(move || stop.store(true, Ordering::Relaxed)) {
+    exit_with(format!("Failed to install Ctrl-C handler: {}", why));
+  }
 
   let logger_thread = std::thread::spawn(move || {
     loop {
       match logger.poll_message() {
-        Ok(_) => (),
+        Ok(true) => (),
+        Ok(false) => break,
         Err(why) => panic!("Polling message failed {}", why)
       }
     }
   });
 
-  gb.load_cartridge(cartridge);
+  if let Err(why) = gb.load_cartridge(cartridge) {
+    exit_with(format!("Failed to load ROM: {}", why));
+  }
+  // Returns after flushing the save file, while the logger can still report it
   gb.start();
+  exit_client.send((LogEvents::Exit, String::from("Exiting")));
   logger_thread.join().unwrap();
 }
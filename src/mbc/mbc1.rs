@@ -0,0 +1,179 @@
+use crate::mbc::{banked_read, banked_write, MemoryBankController, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::mmu::OPEN_BUS;
+
+pub struct Mbc1 {
+  rom: Vec<u8>,
+  ram: Vec<u8>,
+  ram_enabled: bool,
+  /// 5 bit ROM bank register (0x2000-0x3FFF)
+  bank1: u8,
+  /// 2 bit RAM bank / upper ROM bank register (0x4000-0x5FFF)
+  bank2: u8,
+  /// Banking mode select (0x6000-0x7FFF), when set bank2 also applies to
+  /// 0x0000-0x3FFF and to RAM
+  advanced_mode: bool,
+  /// MBC1M multicarts wire bank2 to ROM bit 4 instead of bit 5
+  multicart: bool,
+}
+
+impl Mbc1 {
+  pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+    let multicart = Mbc1::is_multicart(&rom);
+    Mbc1 {
+      rom,
+      ram: vec![0; ram_size],
+      ram_enabled: false,
+      bank1: 1,
+      bank2: 0,
+      advanced_mode: false,
+      multicart,
+    }
+  }
+
+  /// Multicarts are 1MiB images with a second Nintendo logo at the start
+  /// of bank 0x10, where the game selection menu of the second game lives
+  fn is_multicart(rom: &[u8]) -> bool {
+    if rom.len() != 0x100000 {
+      return false;
+    }
+    let logo = 0x104..0x134;
+    let second_logo = (0x10 * ROM_BANK_SIZE + 0x104)..(0x10 * ROM_BANK_SIZE + 0x134);
+    return rom[logo] == rom[second_logo];
+  }
+
+  fn upper_bits(&self) -> usize {
+    let shift = if self.multicart { 4 } else { 5 };
+    return (self.bank2 as usize) << shift;
+  }
+
+  fn low_bank(&self) -> usize {
+    return if self.advanced_mode { self.upper_bits() } else { 0 };
+  }
+
+  fn high_bank(&self) -> usize {
+    let mask = if self.multicart { 0x0F } else { 0x1F };
+    return self.upper_bits() | (self.bank1 & mask) as usize;
+  }
+
+  fn ram_bank(&self) -> usize {
+    return if self.advanced_mode { self.bank2 as usize } else { 0 };
+  }
+}
+
+impl MemoryBankController for Mbc1 {
+  fn read_rom(&self, addr: usize) -> u8 {
+    return match addr {
+      0x0000..=0x3FFF => banked_read(&self.rom, self.low_bank(), ROM_BANK_SIZE, addr),
+      _ => banked_read(&self.rom, self.high_bank(), ROM_BANK_SIZE, addr - 0x4000),
+    }
+  }
+
+  fn write_rom(&mut self, addr: usize, byte: u8) {
+    match addr {
+      0x0000..=0x1FFF => self.ram_enabled = byte & 0x0F == 0x0A,
+      0x2000..=0x3FFF => {
+        // The zero check only looks at the 5 bit register, so banks 0x20,
+        // 0x40 and 0x60 can't be mapped and read as 0x21, 0x41 and 0x61
+        self.bank1 = byte & 0x1F;
+        if self.bank1 == 0 {
+          self.bank1 = 1;
+        }
+      }
+      0x4000..=0x5FFF => self.bank2 = byte & 0x03,
+      _ => self.advanced_mode = byte & 0x01 == 0x01,
+    }
+  }
+
+  fn read_ram(&self, addr: usize) -> u8 {
+    if !self.ram_enabled {
+      return OPEN_BUS;
+    }
+    return banked_read(&self.ram, self.ram_bank(), RAM_BANK_SIZE, addr);
+  }
+
+  fn write_ram(&mut self, addr: usize, byte: u8) {
+    if self.ram_enabled {
+      let bank = self.ram_bank();
+      banked_write(&mut self.ram, bank, RAM_BANK_SIZE, addr, byte);
+    }
+  }
+
+  fn ram_data(&self) -> &[u8] {
+    return &self.ram;
+  }
+
+  fn load_ram_data(&mut self, data: &[u8]) {
+    let len = std::cmp::min(self.ram.len(), data.len());
+    self.ram[..len].copy_from_slice(&data[..len]);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every bank starts with its own bank number
+    fn numbered_rom(banks: usize) -> Vec<u8> {
+      let mut rom = vec![0; banks * ROM_BANK_SIZE];
+      for bank in 0..banks {
+        rom[bank * ROM_BANK_SIZE] = bank as u8;
+      }
+      rom
+    }
+
+    #[test]
+    fn switches_rom_banks_and_skips_bank_zero() {
+      let mut mbc = Mbc1::new(numbered_rom(128), 0);
+      assert_eq!(mbc.read_rom(0x4000), 1);
+      mbc.write_rom(0x2000, 0x05);
+      assert_eq!(mbc.read_rom(0x4000), 5);
+      mbc.write_rom(0x2000, 0x00);
+      assert_eq!(mbc.read_rom(0x4000), 1);
+    }
+
+    #[test]
+    fn bank_0x20_reads_as_0x21() {
+      let mut mbc = Mbc1::new(numbered_rom(128), 0);
+      mbc.write_rom(0x4000, 0x01);
+      mbc.write_rom(0x2000, 0x00);
+      assert_eq!(mbc.read_rom(0x4000), 0x21);
+      assert_eq!(mbc.read_rom(0x0000), 0x00);
+
+      mbc.write_rom(0x6000, 0x01);
+      assert_eq!(mbc.read_rom(0x0000), 0x20);
+    }
+
+    #[test]
+    fn ram_needs_enable_and_banks_in_advanced_mode() {
+      let mut mbc = Mbc1::new(numbered_rom(4), 0x8000);
+      mbc.write_ram(0x0000, 0x11);
+      assert_eq!(mbc.read_ram(0x0000), OPEN_BUS);
+
+      mbc.write_rom(0x0000, 0x0A);
+      mbc.write_ram(0x0000, 0x11);
+      mbc.write_rom(0x4000, 0x02);
+      assert_eq!(mbc.read_ram(0x0000), 0x11);
+
+      mbc.write_rom(0x6000, 0x01);
+      assert_eq!(mbc.read_ram(0x0000), 0x00);
+      mbc.write_ram(0x0000, 0x22);
+      mbc.write_rom(0x6000, 0x00);
+      assert_eq!(mbc.read_ram(0x0000), 0x11);
+    }
+
+    #[test]
+    fn multicart_uses_bank2_as_rom_bit_4() {
+      let mut rom = numbered_rom(64);
+      for i in 0x104..0x134 {
+        rom[i] = i as u8;
+        rom[0x10 * ROM_BANK_SIZE + i] = i as u8;
+      }
+      let mut mbc = Mbc1::new(rom, 0);
+      assert!(mbc.multicart);
+      mbc.write_rom(0x4000, 0x01);
+      mbc.write_rom(0x2000, 0x12);
+      assert_eq!(mbc.read_rom(0x4000), 0x12);
+      mbc.write_rom(0x6000, 0x01);
+      assert_eq!(mbc.read_rom(0x0000), 0x10);
+    }
+}
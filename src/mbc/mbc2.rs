@@ -0,0 +1,109 @@
+use crate::mbc::{banked_read, MemoryBankController, ROM_BANK_SIZE};
+use crate::mmu::OPEN_BUS;
+
+/// 512 half-byte cells built into the controller
+pub const MBC2_RAM_SIZE: usize = 0x200;
+
+pub struct Mbc2 {
+  rom: Vec<u8>,
+  /// Only the lower nibble of every cell is stored
+  ram: [u8; MBC2_RAM_SIZE],
+  ram_enabled: bool,
+  rom_bank: u8,
+}
+
+impl Mbc2 {
+  pub fn new(rom: Vec<u8>) -> Self {
+    Mbc2 {
+      rom,
+      ram: [0; MBC2_RAM_SIZE],
+      ram_enabled: false,
+      rom_bank: 1,
+    }
+  }
+}
+
+impl MemoryBankController for Mbc2 {
+  fn read_rom(&self, addr: usize) -> u8 {
+    return match addr {
+      0x0000..=0x3FFF => banked_read(&self.rom, 0, ROM_BANK_SIZE, addr),
+      _ => banked_read(&self.rom, self.rom_bank as usize, ROM_BANK_SIZE, addr - 0x4000),
+    }
+  }
+
+  fn write_rom(&mut self, addr: usize, byte: u8) {
+    // Both registers live in 0x0000-0x3FFF, address bit 8 selects which one
+    match addr {
+      0x0000..=0x3FFF if addr & 0x100 == 0 => self.ram_enabled = byte & 0x0F == 0x0A,
+      0x0000..=0x3FFF => {
+        self.rom_bank = byte & 0x0F;
+        if self.rom_bank == 0 {
+          self.rom_bank = 1;
+        }
+      }
+      _ => (),
+    }
+  }
+
+  fn read_ram(&self, addr: usize) -> u8 {
+    if !self.ram_enabled {
+      return OPEN_BUS;
+    }
+    // The upper nibble is not connected and reads back as 1s
+    return 0xF0 | self.ram[addr & 0x1FF];
+  }
+
+  fn write_ram(&mut self, addr: usize, byte: u8) {
+    if self.ram_enabled {
+      self.ram[addr & 0x1FF] = byte & 0x0F;
+    }
+  }
+
+  fn ram_data(&self) -> &[u8] {
+    return &self.ram;
+  }
+
+  fn load_ram_data(&mut self, data: &[u8]) {
+    for (cell, byte) in self.ram.iter_mut().zip(data.iter()) {
+      *cell = byte & 0x0F;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_bit_8_selects_register() {
+      let mut rom = vec![0; 16 * ROM_BANK_SIZE];
+      rom[3 * ROM_BANK_SIZE] = 0x33;
+      let mut mbc = Mbc2::new(rom);
+
+      mbc.write_rom(0x2000, 0x03);
+      assert_eq!(mbc.read_rom(0x4000), 0x00);
+      mbc.write_rom(0x2100, 0x03);
+      assert_eq!(mbc.read_rom(0x4000), 0x33);
+
+      mbc.write_rom(0x0100, 0x0A);
+      assert_eq!(mbc.read_ram(0), OPEN_BUS);
+      mbc.write_rom(0x0000, 0x0A);
+      assert_eq!(mbc.read_ram(0), 0xF0);
+    }
+
+    #[test]
+    fn half_byte_ram_is_mirrored() {
+      let mut mbc = Mbc2::new(vec![0; 2 * ROM_BANK_SIZE]);
+      mbc.write_rom(0x0000, 0x0A);
+      mbc.write_ram(0x0005, 0xAB);
+      assert_eq!(mbc.read_ram(0x0005), 0xFB);
+      assert_eq!(mbc.read_ram(0x0205), 0xFB);
+      assert_eq!(mbc.read_ram(0x1E05), 0xFB);
+      assert_eq!(mbc.ram_data()[5], 0x0B);
+
+      let mut restored = Mbc2::new(vec![0; 2 * ROM_BANK_SIZE]);
+      restored.load_ram_data(mbc.ram_data());
+      restored.write_rom(0x0000, 0x0A);
+      assert_eq!(restored.read_ram(0x0005), 0xFB);
+    }
+}
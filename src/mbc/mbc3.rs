@@ -0,0 +1,316 @@
+use crate::mbc::{banked_read, banked_write, MemoryBankController, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::mmu::OPEN_BUS;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Machine cycles in one second at 4.194304 MHz
+const M_CYCLES_PER_SECOND: u32 = 1_048_576;
+
+/// Size of the RTC block appended to save files by BGB and VBA-M
+pub const RTC_FOOTER_SIZE: usize = 48;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct RtcRegisters {
+  pub seconds: u8,
+  pub minutes: u8,
+  pub hours: u8,
+  /// 9 bit day counter
+  pub days: u16,
+  pub halt: bool,
+  /// Set when the day counter overflows, cleared only by the program
+  pub carry: bool,
+}
+
+impl RtcRegisters {
+  fn read(&self, register: u8) -> u8 {
+    return match register {
+      0x08 => self.seconds,
+      0x09 => self.minutes,
+      0x0A => self.hours,
+      0x0B => (self.days & 0xFF) as u8,
+      _ => self.day_high(),
+    }
+  }
+
+  fn write(&mut self, register: u8, byte: u8) {
+    match register {
+      0x08 => self.seconds = byte & 0x3F,
+      0x09 => self.minutes = byte & 0x3F,
+      0x0A => self.hours = byte & 0x1F,
+      0x0B => self.days = (self.days & 0x100) | byte as u16,
+      _ => self.set_day_high(byte),
+    }
+  }
+
+  fn day_high(&self) -> u8 {
+    return ((self.days >> 8) as u8 & 0x01) | ((self.halt as u8) << 6) | ((self.carry as u8) << 7);
+  }
+
+  fn set_day_high(&mut self, byte: u8) {
+    self.days = (self.days & 0xFF) | (((byte & 0x01) as u16) << 8);
+    self.halt = byte & 0x40 != 0;
+    self.carry = byte & 0x80 != 0;
+  }
+
+  pub fn advance(&mut self, seconds: u64) {
+    if self.halt {
+      return;
+    }
+    let total = self.seconds as u64 + seconds;
+    self.seconds = (total % 60) as u8;
+    let minutes = self.minutes as u64 + total / 60;
+    self.minutes = (minutes % 60) as u8;
+    let hours = self.hours as u64 + minutes / 60;
+    self.hours = (hours % 24) as u8;
+    let days = self.days as u64 + hours / 24;
+    if days > 0x1FF {
+      self.carry = true;
+    }
+    self.days = (days % 0x200) as u16;
+  }
+}
+
+pub struct Rtc {
+  pub live: RtcRegisters,
+  pub latched: RtcRegisters,
+  cycles: u32,
+}
+
+impl Rtc {
+  pub fn new() -> Self {
+    Rtc { live: RtcRegisters::default(), latched: RtcRegisters::default(), cycles: 0 }
+  }
+
+  pub fn tick(&mut self, cycles: u8) {
+    if self.live.halt {
+      return;
+    }
+    self.cycles += cycles as u32;
+    if self.cycles >= M_CYCLES_PER_SECOND {
+      self.cycles -= M_CYCLES_PER_SECOND;
+      self.live.advance(1);
+    }
+  }
+
+  /// Live and latched registers as 32 bit little endian words followed by
+  /// the 64 bit unix timestamp of when the footer was written
+  pub fn to_footer(&self) -> Vec<u8> {
+    let mut footer = Vec::with_capacity(RTC_FOOTER_SIZE);
+    for regs in [&self.live, &self.latched].iter() {
+      for register in 0x08..=0x0C {
+        footer.extend_from_slice(&(regs.read(register) as u32).to_le_bytes());
+      }
+    }
+    footer.extend_from_slice(&unix_now().to_le_bytes());
+    return footer;
+  }
+
+  /// Restores the clock and catches up on the time spent powered off.
+  /// Also accepts the 44 byte variant with a 32 bit timestamp.
+  pub fn load_footer(&mut self, footer: &[u8]) -> Result<(), String> {
+    if footer.len() < 44 {
+      return Err(format!("RTC footer is {} bytes long", footer.len()));
+    }
+    let word = |i: usize| u32::from_le_bytes([footer[i * 4], footer[i * 4 + 1], footer[i * 4 + 2], footer[i * 4 + 3]]);
+    for register in 0x08..=0x0C {
+      let i = (register - 0x08) as usize;
+      self.live.write(register, word(i) as u8);
+      self.latched.write(register, word(i + 5) as u8);
+    }
+    let saved_at = match footer.len() {
+      l if l >= RTC_FOOTER_SIZE => {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&footer[40..48]);
+        u64::from_le_bytes(bytes)
+      }
+      _ => word(10) as u64,
+    };
+    self.live.advance(unix_now().saturating_sub(saved_at));
+    Ok(())
+  }
+}
+
+fn unix_now() -> u64 {
+  return SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+}
+
+pub struct Mbc3 {
+  rom: Vec<u8>,
+  ram: Vec<u8>,
+  pub rtc: Option<Rtc>,
+  ram_enabled: bool,
+  rom_bank: u8,
+  /// RAM bank (0x00-0x03) or RTC register (0x08-0x0C) mapped at 0xA000
+  select: u8,
+  /// Latching needs a 0x00 write followed by 0x01
+  latch_armed: bool,
+}
+
+impl Mbc3 {
+  pub fn new(rom: Vec<u8>, ram_size: usize, has_timer: bool) -> Self {
+    Mbc3 {
+      rom,
+      ram: vec![0; ram_size],
+      rtc: if has_timer { Some(Rtc::new()) } else { None },
+      ram_enabled: false,
+      rom_bank: 1,
+      select: 0,
+      latch_armed: false,
+    }
+  }
+}
+
+impl MemoryBankController for Mbc3 {
+  fn read_rom(&self, addr: usize) -> u8 {
+    return match addr {
+      0x0000..=0x3FFF => banked_read(&self.rom, 0, ROM_BANK_SIZE, addr),
+      _ => banked_read(&self.rom, self.rom_bank as usize, ROM_BANK_SIZE, addr - 0x4000),
+    }
+  }
+
+  fn write_rom(&mut self, addr: usize, byte: u8) {
+    match addr {
+      0x0000..=0x1FFF => self.ram_enabled = byte & 0x0F == 0x0A,
+      0x2000..=0x3FFF => {
+        self.rom_bank = byte & 0x7F;
+        if self.rom_bank == 0 {
+          self.rom_bank = 1;
+        }
+      }
+      0x4000..=0x5FFF => self.select = byte,
+      _ => {
+        if self.latch_armed && byte == 0x01 {
+          if let Some(rtc) = self.rtc.as_mut() {
+            rtc.latched = rtc.live;
+          }
+        }
+        self.latch_armed = byte == 0x00;
+      }
+    }
+  }
+
+  fn read_ram(&self, addr: usize) -> u8 {
+    if !self.ram_enabled {
+      return OPEN_BUS;
+    }
+    return match (self.select, self.rtc.as_ref()) {
+      (0x00..=0x07, _) => banked_read(&self.ram, self.select as usize, RAM_BANK_SIZE, addr),
+      (0x08..=0x0C, Some(rtc)) => rtc.latched.read(self.select),
+      _ => OPEN_BUS,
+    }
+  }
+
+  fn write_ram(&mut self, addr: usize, byte: u8) {
+    if !self.ram_enabled {
+      return;
+    }
+    match (self.select, self.rtc.as_mut()) {
+      (0x00..=0x07, _) => banked_write(&mut self.ram, self.select as usize, RAM_BANK_SIZE, addr, byte),
+      (0x08..=0x0C, Some(rtc)) => {
+        if self.select == 0x08 {
+          rtc.cycles = 0;
+        }
+        rtc.live.write(self.select, byte);
+      }
+      _ => (),
+    }
+  }
+
+  fn tick(&mut self, cycles: u8) {
+    if let Some(rtc) = self.rtc.as_mut() {
+      rtc.tick(cycles);
+    }
+  }
+
+  fn ram_data(&self) -> &[u8] {
+    return &self.ram;
+  }
+
+  fn load_ram_data(&mut self, data: &[u8]) {
+    let len = std::cmp::min(self.ram.len(), data.len());
+    self.ram[..len].copy_from_slice(&data[..len]);
+  }
+
+  fn rtc_data(&self) -> Option<Vec<u8>> {
+    return self.rtc.as_ref().map(|rtc| rtc.to_footer());
+  }
+
+  fn load_rtc_data(&mut self, data: &[u8]) -> Result<(), String> {
+    return match self.rtc.as_mut() {
+      Some(rtc) => rtc.load_footer(data),
+      None => Ok(()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_mbc() -> Mbc3 {
+      let mut mbc = Mbc3::new(vec![0; 4 * ROM_BANK_SIZE], 0x8000, true);
+      mbc.write_rom(0x0000, 0x0A);
+      mbc
+    }
+
+    fn latch(mbc: &mut Mbc3) {
+      mbc.write_rom(0x6000, 0x00);
+      mbc.write_rom(0x6000, 0x01);
+    }
+
+    #[test]
+    fn clock_advances_from_cycles_and_is_read_through_latch() {
+      let mut mbc = enabled_mbc();
+      for _ in 0..(M_CYCLES_PER_SECOND / 4) {
+        mbc.tick(4);
+      }
+      mbc.write_rom(0x4000, 0x08);
+      assert_eq!(mbc.read_ram(0), 0);
+
+      latch(&mut mbc);
+      assert_eq!(mbc.read_ram(0), 1);
+      mbc.write_rom(0x4000, 0x09);
+      assert_eq!(mbc.read_ram(0), 0);
+    }
+
+    #[test]
+    fn day_counter_overflow_sets_carry_and_halt_stops_clock() {
+      let mut regs = RtcRegisters { days: 0x1FF, hours: 23, minutes: 59, seconds: 59, ..Default::default() };
+      regs.advance(1);
+      assert_eq!(regs.days, 0);
+      assert!(regs.carry);
+      assert_eq!(regs.read(0x0C), 0x80);
+
+      regs.write(0x0C, 0x40);
+      regs.advance(100);
+      assert_eq!(regs.seconds, 0);
+      assert!(!regs.carry);
+    }
+
+    #[test]
+    fn footer_round_trip_catches_up_wall_clock() {
+      let mut rtc = Rtc::new();
+      rtc.live.minutes = 5;
+      let mut footer = rtc.to_footer();
+      assert_eq!(footer.len(), RTC_FOOTER_SIZE);
+
+      // Pretend the footer was written an hour ago
+      let saved_at = unix_now() - 3600;
+      footer[40..48].copy_from_slice(&saved_at.to_le_bytes());
+      let mut restored = Rtc::new();
+      restored.load_footer(&footer).unwrap();
+      assert_eq!(restored.live.hours, 1);
+      assert_eq!(restored.live.minutes, 5);
+      assert_eq!(restored.latched.minutes, 0);
+    }
+
+    #[test]
+    fn ram_banks_are_selected_through_the_same_register() {
+      let mut mbc = enabled_mbc();
+      mbc.write_rom(0x4000, 0x01);
+      mbc.write_ram(0x10, 0xAB);
+      mbc.write_rom(0x4000, 0x00);
+      assert_eq!(mbc.read_ram(0x10), 0x00);
+      mbc.write_rom(0x4000, 0x01);
+      assert_eq!(mbc.read_ram(0x10), 0xAB);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::mbc::{banked_read, banked_write, MemoryBankController, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::mmu::OPEN_BUS;
+
+pub struct Mbc5 {
+  rom: Vec<u8>,
+  ram: Vec<u8>,
+  ram_enabled: bool,
+  /// 9 bit ROM bank number, bank 0 can be mapped at 0x4000
+  rom_bank: u16,
+  ram_bank: u8,
+  /// Rumble carts wire bit 3 of the RAM bank register to the motor
+  has_rumble: bool,
+  motor: bool,
+}
+
+impl Mbc5 {
+  pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> Self {
+    Mbc5 {
+      rom,
+      ram: vec![0; ram_size],
+      ram_enabled: false,
+      rom_bank: 1,
+      ram_bank: 0,
+      has_rumble,
+      motor: false,
+    }
+  }
+}
+
+impl MemoryBankController for Mbc5 {
+  fn read_rom(&self, addr: usize) -> u8 {
+    return match addr {
+      0x0000..=0x3FFF => banked_read(&self.rom, 0, ROM_BANK_SIZE, addr),
+      _ => banked_read(&self.rom, self.rom_bank as usize, ROM_BANK_SIZE, addr - 0x4000),
+    }
+  }
+
+  fn write_rom(&mut self, addr: usize, byte: u8) {
+    match addr {
+      0x0000..=0x1FFF => self.ram_enabled = byte == 0x0A,
+      0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | byte as u16,
+      0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | (((byte & 0x01) as u16) << 8),
+      0x4000..=0x5FFF => {
+        if self.has_rumble {
+          self.motor = byte & 0x08 != 0;
+          self.ram_bank = byte & 0x07;
+        } else {
+          self.ram_bank = byte & 0x0F;
+        }
+      }
+      _ => (),
+    }
+  }
+
+  fn read_ram(&self, addr: usize) -> u8 {
+    if !self.ram_enabled {
+      return OPEN_BUS;
+    }
+    return banked_read(&self.ram, self.ram_bank as usize, RAM_BANK_SIZE, addr);
+  }
+
+  fn write_ram(&mut self, addr: usize, byte: u8) {
+    if self.ram_enabled {
+      banked_write(&mut self.ram, self.ram_bank as usize, RAM_BANK_SIZE, addr, byte);
+    }
+  }
+
+  fn rumble(&self) -> bool {
+    return self.motor;
+  }
+
+  fn ram_data(&self) -> &[u8] {
+    return &self.ram;
+  }
+
+  fn load_ram_data(&mut self, data: &[u8]) {
+    let len = std::cmp::min(self.ram.len(), data.len());
+    self.ram[..len].copy_from_slice(&data[..len]);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nine_bit_rom_bank_including_bank_zero() {
+      let mut rom = vec![0; 512 * ROM_BANK_SIZE];
+      rom[0x1FF * ROM_BANK_SIZE] = 0xAB;
+      rom[0x100 * ROM_BANK_SIZE] = 0xCD;
+      rom[0] = 0xEF;
+      let mut mbc = Mbc5::new(rom, 0, false);
+
+      mbc.write_rom(0x2000, 0xFF);
+      mbc.write_rom(0x3000, 0x01);
+      assert_eq!(mbc.read_rom(0x4000), 0xAB);
+      mbc.write_rom(0x2000, 0x00);
+      assert_eq!(mbc.read_rom(0x4000), 0xCD);
+      mbc.write_rom(0x3000, 0x00);
+      assert_eq!(mbc.read_rom(0x4000), 0xEF);
+    }
+
+    #[test]
+    fn rumble_bit_is_not_part_of_ram_bank() {
+      let mut mbc = Mbc5::new(vec![0; 2 * ROM_BANK_SIZE], 0x8000, true);
+      mbc.write_rom(0x0000, 0x0A);
+      mbc.write_rom(0x4000, 0x01);
+      mbc.write_ram(0x0000, 0x42);
+      assert!(!mbc.rumble());
+
+      mbc.write_rom(0x4000, 0x09);
+      assert!(mbc.rumble());
+      assert_eq!(mbc.read_ram(0x0000), 0x42);
+    }
+}
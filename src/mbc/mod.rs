@@ -0,0 +1,86 @@
+pub mod rom_only;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+
+use crate::external::cartridge::{Cartridge, CartridgeType};
+use crate::external::error::LoadError;
+use crate::mbc::mbc1::Mbc1;
+use crate::mbc::mbc2::Mbc2;
+use crate::mbc::mbc3::Mbc3;
+use crate::mbc::mbc5::Mbc5;
+use crate::mbc::rom_only::RomOnly;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+/// Cartridge hardware sitting behind 0x0000-0x7FFF and 0xA000-0xBFFF.
+/// ROM addresses are absolute, RAM addresses are relative to 0xA000.
+pub trait MemoryBankController {
+  fn read_rom(&self, addr: usize) -> u8;
+  /// Writes to the ROM area program the controller registers
+  fn write_rom(&mut self, addr: usize, byte: u8);
+  fn read_ram(&self, addr: usize) -> u8;
+  fn write_ram(&mut self, addr: usize, byte: u8);
+  /// Advances clocks living on the cartridge, such as the MBC3 RTC
+  fn tick(&mut self, _cycles: u8) {}
+  /// Whether the cartridge is currently driving a rumble motor
+  fn rumble(&self) -> bool {
+    return false;
+  }
+  /// External RAM contents as stored in battery save files
+  fn ram_data(&self) -> &[u8] {
+    return &[];
+  }
+  fn load_ram_data(&mut self, _data: &[u8]) {}
+  /// Clock state appended after the RAM in battery save files
+  fn rtc_data(&self) -> Option<Vec<u8>> {
+    return None;
+  }
+  fn load_rtc_data(&mut self, _data: &[u8]) -> Result<(), String> {
+    return Ok(());
+  }
+}
+
+pub fn from_cartridge(cartridge: &Cartridge) -> Result<Box<dyn MemoryBankController>, LoadError> {
+  let rom = cartridge.content.clone();
+  let ram_size = cartridge.ram_size().bytes();
+  return match cartridge.cartridge_type() {
+    CartridgeType::ROM_ONLY | CartridgeType::ROM_RAM | CartridgeType::ROM_RAM_BATTERY =>
+      Ok(Box::new(RomOnly::new(rom, ram_size)?)),
+    CartridgeType::MBC1 | CartridgeType::MBC1_RAM | CartridgeType::MBC1_RAM_BATTERY =>
+      Ok(Box::new(Mbc1::new(rom, ram_size))),
+    CartridgeType::MBC2 | CartridgeType::MBC2_BATTERY =>
+      Ok(Box::new(Mbc2::new(rom))),
+    CartridgeType::MBC3 | CartridgeType::MBC3_RAM | CartridgeType::MBC3_RAM_BATTERY =>
+      Ok(Box::new(Mbc3::new(rom, ram_size, false))),
+    CartridgeType::MBC3_TIMER_BATTERY | CartridgeType::MBC3_TIMER_RAM_BATTERY =>
+      Ok(Box::new(Mbc3::new(rom, ram_size, true))),
+    CartridgeType::MBC5 | CartridgeType::MBC5_RAM | CartridgeType::MBC5_RAM_BATTERY =>
+      Ok(Box::new(Mbc5::new(rom, ram_size, false))),
+    CartridgeType::MBC5_RUMBLE | CartridgeType::MBC5_RUMBLE_RAM | CartridgeType::MBC5_RUMBLE_RAM_BATTERY =>
+      Ok(Box::new(Mbc5::new(rom, ram_size, true))),
+    _ => Err(LoadError::UnsupportedMapper(cartridge.content[0x147])),
+  }
+}
+
+/// Byte at `bank` * `bank_size` + `offset`, wrapping the bank number around
+/// the banks actually present in `data`. Chips smaller than a bank are mirrored.
+pub fn banked_read(data: &[u8], bank: usize, bank_size: usize, offset: usize) -> u8 {
+  if data.is_empty() {
+    return 0xFF;
+  }
+  let banks = std::cmp::max(1, data.len() / bank_size);
+  let index = ((bank % banks) * bank_size + offset) % data.len();
+  return data[index];
+}
+
+pub fn banked_write(data: &mut [u8], bank: usize, bank_size: usize, offset: usize, byte: u8) {
+  if data.is_empty() {
+    return;
+  }
+  let banks = std::cmp::max(1, data.len() / bank_size);
+  let index = ((bank % banks) * bank_size + offset) % data.len();
+  data[index] = byte;
+}
@@ -0,0 +1,45 @@
+use crate::external::error::LoadError;
+use crate::mbc::MemoryBankController;
+use crate::mmu::{OPEN_BUS, ROM_SIZE};
+
+/// 32KiB of ROM with up to 8KiB of RAM and no bank switching
+pub struct RomOnly {
+  rom: Vec<u8>,
+  ram: Vec<u8>,
+}
+
+impl RomOnly {
+  pub fn new(rom: Vec<u8>, ram_size: usize) -> Result<Self, LoadError> {
+    if rom.len() > ROM_SIZE {
+      return Err(LoadError::RomTooLarge { size: rom.len(), max: ROM_SIZE })
+    }
+    Ok(RomOnly { rom, ram: vec![0; ram_size] })
+  }
+}
+
+impl MemoryBankController for RomOnly {
+  fn read_rom(&self, addr: usize) -> u8 {
+    return *self.rom.get(addr).unwrap_or(&OPEN_BUS);
+  }
+
+  fn write_rom(&mut self, _addr: usize, _byte: u8) {}
+
+  fn read_ram(&self, addr: usize) -> u8 {
+    return *self.ram.get(addr).unwrap_or(&OPEN_BUS);
+  }
+
+  fn write_ram(&mut self, addr: usize, byte: u8) {
+    if let Some(b) = self.ram.get_mut(addr) {
+      *b = byte;
+    }
+  }
+
+  fn ram_data(&self) -> &[u8] {
+    return &self.ram;
+  }
+
+  fn load_ram_data(&mut self, data: &[u8]) {
+    let len = std::cmp::min(self.ram.len(), data.len());
+    self.ram[..len].copy_from_slice(&data[..len]);
+  }
+}
@@ -1,6 +1,13 @@
 use crate::debug::logger::LogMessage;
 use crate::debug::logger::LogEvents;
 use crate::debug::logger::LoggableComponent;
+use crate::cpu::interrupt::InterruptController;
+use crate::dma::OamDma;
+use crate::external::cartridge::Cartridge;
+use crate::external::error::LoadError;
+use crate::mbc::{self, MemoryBankController};
+use crate::mbc::rom_only::RomOnly;
+use crate::ppu::core::{Ppu, Renderer};
 use std::{thread, time};
 
 // pub struct MemoryBus {
@@ -24,48 +31,179 @@ use std::{thread, time};
 //   }
 // }
 
+pub const ROM_SIZE: usize = 0x8000;
+pub const VRAM_SIZE: usize = 0x2000;
+pub const WRAM_SIZE: usize = 0x2000;
+pub const OAM_SIZE: usize = 0xA0;
+pub const IO_SIZE: usize = 0x80;
+pub const HRAM_SIZE: usize = 0x7F;
+
+/// Value seen on the data bus when nothing drives it
+pub const OPEN_BUS: u8 = 0xFF;
+
 pub struct VirtualMemory {
-  pub data: Vec<u8>,
+  /// Mapped over the start of the cartridge until 0xFF50 is written
+  pub boot_rom: Option<Vec<u8>>,
+  /// Cartridge ROM and external RAM
+  pub mbc: Box<dyn MemoryBankController>,
+  /// Owns VRAM, OAM and the LCD registers
+  pub ppu: Ppu,
+  pub dma: OamDma,
+  pub wram: [u8; WRAM_SIZE],
+  pub io: [u8; IO_SIZE],
+  pub hram: [u8; HRAM_SIZE],
+  pub interrupts: InterruptController,
   message_buffer: Vec<LogMessage>
 }
 
 impl VirtualMemory {
 
-  pub fn from_rom(raw: &[u8]) -> Result<Self,String> {
-    if raw.len() >= 0xFFFF {
-      return Err(String::from("Rom is bigger than target system memory"))
-    }
-    let mut data = vec![0;0xFFFF];
-    data[0..raw.len()].copy_from_slice(&raw);
-    Ok(VirtualMemory {data, message_buffer: vec!() })
+  pub fn from_rom(raw: &[u8]) -> Result<Self,LoadError> {
+    let mut mmu = VirtualMemory::new();
+    mmu.load_rom(raw)?;
+    Ok(mmu)
   }
 
   pub fn new() -> Self {
+    VirtualMemory::with_renderer(Renderer::Scanline)
+  }
+
+  pub fn with_renderer(renderer: Renderer) -> Self {
     VirtualMemory {
-      data: vec![0;0xFFFF],
+      boot_rom: None,
+      mbc: Box::new(RomOnly::new(vec!(), 0).unwrap()),
+      ppu: Ppu::with_renderer(renderer),
+      dma: OamDma::new(),
+      wram: [0; WRAM_SIZE],
+      io: [0; IO_SIZE],
+      hram: [0; HRAM_SIZE],
+      interrupts: InterruptController::new(),
       message_buffer: vec!()
     }
   }
 
-  pub fn load_rom(&mut self, raw: &[u8]) -> Result<usize,String> {
-    if raw.len() >= 0xFFFF {
-      return Err(String::from("Rom is bigger than target system memory"))
+  /// Maps a raw image without a bank controller
+  pub fn load_rom(&mut self, raw: &[u8]) -> Result<usize,LoadError> {
+    self.mbc = Box::new(RomOnly::new(raw.to_vec(), 0)?);
+    Ok(raw.len())
+  }
+
+  pub fn load_cartridge(&mut self, cartridge: &Cartridge) -> Result<usize,LoadError> {
+    self.mbc = mbc::from_cartridge(cartridge)?;
+    Ok(cartridge.content.len())
+  }
+
+  pub fn load_boot_rom(&mut self, raw: &[u8]) -> Result<usize,LoadError> {
+    if raw.len() > 0x900 {
+      return Err(LoadError::BootRomTooLarge { size: raw.len() })
     }
-    self.data[0..raw.len()].copy_from_slice(&raw);
+    self.boot_rom = Some(raw.to_vec());
     Ok(raw.len())
   }
 
+  /// Sets hardware registers directly, skipping any write side effects
+  pub fn load_io_state(&mut self, registers: &[(usize, u8)]) {
+    for (addr, byte) in registers {
+      match addr {
+        0xFF0F => self.interrupts.write_flag(*byte),
+        0xFFFF => self.interrupts.enable = *byte,
+        0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.load_register(*addr, *byte),
+        0xFF46 => self.dma.register = *byte,
+        0xFF00..=0xFF7F => self.io[addr - 0xFF00] = *byte,
+        _ => (),
+      }
+    }
+  }
+
+  fn boot_rom_byte(&self, addr: usize) -> Option<u8> {
+    // CGB boot roms leave the cartridge header at 0x100-0x1FF visible
+    if (0x100..0x200).contains(&addr) {
+      return None;
+    }
+    return self.boot_rom.as_ref().and_then(|boot| boot.get(addr).copied());
+  }
+
+  /// Advances every component on the bus by `cycles` machine cycles
+  pub fn tick(&mut self, cycles: u8) {
+    self.mbc.tick(cycles);
+    for _ in 0..cycles {
+      if let Some((source, offset)) = self.dma.step() {
+        self.ppu.oam[offset] = self.peek(source);
+      }
+    }
+    self.ppu.tick(cycles, &mut self.interrupts);
+  }
+
+  /// While OAM DMA runs the CPU only reaches HRAM and the registers, the rest
+  /// of the bus belongs to the transfer
+  fn blocked_by_dma(&self, addr: usize) -> bool {
+    return self.dma.is_active() && addr < 0xFF00;
+  }
+
+  /// Reads a byte without side effects or logging, used by the debugger
+  pub fn peek(&self, addr: usize) -> u8 {
+    if let Some(byte) = self.boot_rom_byte(addr) {
+      return byte;
+    }
+    return match addr {
+      0x0000..=0x7FFF => self.mbc.read_rom(addr),
+      0x8000..=0x9FFF => self.ppu.vram[addr - 0x8000],
+      0xA000..=0xBFFF => self.mbc.read_ram(addr - 0xA000),
+      0xC000..=0xDFFF => self.wram[addr - 0xC000],
+      // Echo RAM mirrors 0xC000-0xDDFF
+      0xE000..=0xFDFF => self.wram[addr - 0xE000],
+      0xFE00..=0xFE9F => self.ppu.oam[addr - 0xFE00],
+      0xFEA0..=0xFEFF => 0x00,
+      0xFF0F => self.interrupts.read_flag(),
+      0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.read_register(addr),
+      0xFF46 => self.dma.register,
+      0xFF50 => OPEN_BUS,
+      0xFF00..=0xFF7F => self.io[addr - 0xFF00],
+      0xFF80..=0xFFFE => self.hram[addr - 0xFF80],
+      0xFFFF => self.interrupts.enable,
+      _ => OPEN_BUS,
+    }
+  }
+
+  /// Debugger view of `start..end`
+  pub fn peek_range(&self, start: usize, end: usize) -> Vec<u8> {
+    return (start..end).map(|addr| self.peek(addr)).collect();
+  }
+
   pub fn fetch(&mut self, pointer: usize) -> u8 {
-    // let ten_millis = time::Duration::from_millis(1);
-    // thread::sleep(ten_millis);
-    let byte = self.data[pointer];
+    let byte = if self.blocked_by_dma(pointer) { OPEN_BUS } else { self.peek(pointer) };
     self.message_buffer.push((LogEvents::MemoryFetch, format!("[FETCH] ADDR({:#06x}): {:#04x}", pointer, byte)));
-    // println!("READ [{:#06x}]: {:#04x}", pointer, byte);
     return byte;
   }
 
   pub fn save(&mut self, addr: usize, byte: u8) -> Result<(),String> {
-    self.data[addr] = byte;
+    if self.blocked_by_dma(addr) {
+      return Ok(());
+    }
+    match addr {
+      0x0000..=0x7FFF => self.mbc.write_rom(addr, byte),
+      0x8000..=0x9FFF => self.ppu.vram[addr - 0x8000] = byte,
+      0xA000..=0xBFFF => self.mbc.write_ram(addr - 0xA000, byte),
+      0xC000..=0xDFFF => self.wram[addr - 0xC000] = byte,
+      0xE000..=0xFDFF => self.wram[addr - 0xE000] = byte,
+      0xFE00..=0xFE9F => self.ppu.oam[addr - 0xFE00] = byte,
+      0xFEA0..=0xFEFF => (),
+      // Any write resets DIV
+      0xFF04 => self.io[0x04] = 0x00,
+      0xFF0F => self.interrupts.write_flag(byte),
+      0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.write_register(addr, byte, &mut self.interrupts),
+      0xFF46 => self.dma.start(byte),
+      0xFF50 => {
+        if byte & 0x01 != 0 && self.boot_rom.is_some() {
+          self.boot_rom = None;
+          self.message_buffer.push((LogEvents::Initializing, String::from("Boot rom unmapped")));
+        }
+      }
+      0xFF00..=0xFF7F => self.io[addr - 0xFF00] = byte,
+      0xFF80..=0xFFFE => self.hram[addr - 0xFF80] = byte,
+      0xFFFF => self.interrupts.enable = byte,
+      _ => return Err(format!("Address out of range {:#06x}", addr)),
+    };
     let log = match addr {
       0x8000..=0x9FFF => (LogEvents::VramSave, format!("[VRAM_SAVE] ADDR({:#06x}): {:#04x}", addr, byte)),
       _ => (LogEvents::MemorySave, format!("[SAVE] ADDR({:#06x}): {:#04x}", addr, byte)),
@@ -94,14 +232,125 @@ mod tests {
       let rom = vec![0xFF;0x4000];
       match VirtualMemory::from_rom(&rom.as_slice()) {
         Ok(mmu) => {
-          assert_eq!(mmu.data.len(), 0xFFFF);
-          assert_eq!(mmu.data[0x0], 0xFF);
-          assert_eq!(mmu.data[0xFF], 0xFF);
-          assert_eq!(mmu.data[0x3999], 0xFF);
-          assert_eq!(mmu.data[0x4000], 0x0);
-          assert_eq!(mmu.data[0x4001], 0x0);
+          assert_eq!(mmu.peek(0x0), 0xFF);
+          assert_eq!(mmu.peek(0xFF), 0xFF);
+          assert_eq!(mmu.peek(0x3999), 0xFF);
+          assert_eq!(mmu.peek(0x8000), 0x0);
+          assert_eq!(mmu.peek(0xC000), 0x0);
         }
         _ => panic!("Failed")
       }
     }
+
+    #[test]
+    fn rom_is_read_only_and_rejects_oversized_images() {
+      let mut mmu = VirtualMemory::from_rom(&[0x12; 0x100]).unwrap();
+      mmu.save(0x0000, 0x34).unwrap();
+      assert_eq!(mmu.fetch(0x0000), 0x12);
+      assert_eq!(mmu.fetch(0x0100), OPEN_BUS);
+      assert!(VirtualMemory::from_rom(&vec![0; 0x8001]).is_err());
+    }
+
+    #[test]
+    fn echo_ram_mirrors_work_ram() {
+      let mut mmu = VirtualMemory::new();
+      mmu.save(0xC123, 0xAB).unwrap();
+      assert_eq!(mmu.fetch(0xE123), 0xAB);
+      mmu.save(0xFDFF, 0xCD).unwrap();
+      assert_eq!(mmu.fetch(0xDDFF), 0xCD);
+    }
+
+    #[test]
+    fn unusable_area_ignores_writes_and_ie_is_addressable() {
+      let mut mmu = VirtualMemory::new();
+      mmu.save(0xFEA0, 0xAB).unwrap();
+      assert_eq!(mmu.fetch(0xFEA0), 0x00);
+      assert_eq!(mmu.fetch(0xA000), OPEN_BUS);
+      mmu.save(0xFFFF, 0x1F).unwrap();
+      assert_eq!(mmu.fetch(0xFFFF), 0x1F);
+      mmu.save(0xFFFE, 0x42).unwrap();
+      assert_eq!(mmu.hram[0x7E], 0x42);
+    }
+
+    #[test]
+    fn writing_div_clears_it() {
+      let mut mmu = VirtualMemory::new();
+      mmu.load_io_state(&[(0xFF04, 0xAB)]);
+      assert_eq!(mmu.fetch(0xFF04), 0xAB);
+      mmu.save(0xFF04, 0x12).unwrap();
+      assert_eq!(mmu.fetch(0xFF04), 0x00);
+    }
+
+    #[test]
+    fn boot_rom_overlays_cartridge_until_ff50_write() {
+      let mut mmu = VirtualMemory::from_rom(&[0xAA; 0x200]).unwrap();
+      mmu.load_boot_rom(&[0x31; 0x100]).unwrap();
+      assert_eq!(mmu.fetch(0x0000), 0x31);
+      assert_eq!(mmu.fetch(0x00FF), 0x31);
+      assert_eq!(mmu.fetch(0x0100), 0xAA);
+
+      mmu.save(0xFF50, 0x00).unwrap();
+      assert_eq!(mmu.fetch(0x0000), 0x31);
+      mmu.save(0xFF50, 0x01).unwrap();
+      assert_eq!(mmu.fetch(0x0000), 0xAA);
+      assert_eq!(mmu.fetch(0x00FF), 0xAA);
+    }
+
+    #[test]
+    fn oam_dma_copies_a_page_and_locks_the_bus() {
+      let mut mmu = VirtualMemory::from_rom(&[0x3C; 0x8000]).unwrap();
+      for i in 0..OAM_SIZE {
+        mmu.save(0xC100 + i, i as u8).unwrap();
+      }
+      mmu.save(0xFF80, 0x42).unwrap();
+      mmu.save(0xFF46, 0xC1).unwrap();
+      assert_eq!(mmu.fetch(0xFF46), 0xC1);
+
+      mmu.tick(1);
+      assert_eq!(mmu.fetch(0x0000), OPEN_BUS);
+      assert_eq!(mmu.fetch(0xC100), OPEN_BUS);
+      assert_eq!(mmu.fetch(0xFF80), 0x42);
+      mmu.save(0xC000, 0x99).unwrap();
+
+      mmu.tick(159);
+      assert_eq!(mmu.fetch(0x0000), OPEN_BUS);
+      mmu.tick(1);
+      assert_eq!(mmu.fetch(0x0000), 0x3C);
+      assert_eq!(mmu.fetch(0xC000), 0x00);
+      assert_eq!(mmu.peek_range(0xFE00, 0xFEA0), (0..OAM_SIZE as u8).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn restarting_oam_dma_copies_the_new_page_from_the_start() {
+      let mut mmu = VirtualMemory::new();
+      mmu.wram[0x0000..OAM_SIZE].copy_from_slice(&[0x11; OAM_SIZE]);
+      mmu.wram[0x0100..0x0100 + OAM_SIZE].copy_from_slice(&[0x22; OAM_SIZE]);
+      mmu.save(0xFF46, 0xC0).unwrap();
+      mmu.tick(81);
+      assert_eq!(mmu.ppu.oam[79], 0x11);
+      assert_eq!(mmu.ppu.oam[80], 0x00);
+
+      mmu.save(0xFF46, 0xC1).unwrap();
+      mmu.tick(1);
+      assert_eq!(mmu.ppu.oam[80], 0x11);
+      mmu.tick(OAM_SIZE as u8 - 1);
+      assert_eq!(mmu.fetch(0xC000), OPEN_BUS);
+      mmu.tick(1);
+      assert_eq!(mmu.ppu.oam, [0x22; OAM_SIZE]);
+      assert_eq!(mmu.fetch(0xC000), 0x11);
+    }
+
+    #[test]
+    fn ly_reaches_the_line_the_boot_rom_waits_for() {
+      let mut mmu = VirtualMemory::new();
+      mmu.save(0xFF40, 0x80).unwrap();
+      let mut cycles = 0;
+      while mmu.fetch(0xFF44) != 0x90 {
+        mmu.tick(1);
+        cycles += 1;
+        assert!(cycles < 70224 / 4, "LY never reached 0x90");
+      }
+      assert_eq!(cycles, 0x90 * 456 / 4);
+      assert_ne!(mmu.fetch(0xFF0F) & 0x01, 0);
+    }
 }
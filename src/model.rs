@@ -0,0 +1,135 @@
+use crate::reg::Registers;
+
+/// Hardware revision, used to pick the state the boot rom leaves behind
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+  DMG0,
+  DMG,
+  MGB,
+  SGB,
+  SGB2,
+  CGB,
+}
+
+impl Model {
+  pub fn from_name(name: &str) -> Option<Model> {
+    return match name.to_lowercase().as_str() {
+      "dmg0" => Some(Model::DMG0),
+      "dmg" => Some(Model::DMG),
+      "mgb" => Some(Model::MGB),
+      "sgb" => Some(Model::SGB),
+      "sgb2" => Some(Model::SGB2),
+      "cgb" => Some(Model::CGB),
+      _ => None,
+    }
+  }
+
+  /// CPU registers at 0x0100 after the boot rom hands over control.
+  /// On DMG and MGB the H and C flags depend on the cartridge header checksum.
+  pub fn post_boot_registers(&self, header_checksum: u8) -> Registers {
+    let checksum_flags = if header_checksum == 0x00 { 0x80 } else { 0xB0 };
+    let (a, f, b, c, d, e, h, l) = match self {
+      Model::DMG0 => (0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03),
+      Model::DMG => (0x01, checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+      Model::MGB => (0xFF, checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+      Model::SGB => (0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+      Model::SGB2 => (0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+      Model::CGB => (0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D),
+    };
+    Registers { a, f, b, c, d, e, h, l, sp: 0xFFFE, pc: 0x0100 }
+  }
+
+  /// Hardware registers after the boot rom. DIV is not deterministic on
+  /// SGB and CGB since it depends on how long the boot sequence took.
+  pub fn post_boot_io(&self) -> Vec<(usize, u8)> {
+    let div = match self {
+      Model::DMG0 => 0x18,
+      Model::DMG | Model::MGB => 0xAB,
+      _ => 0x00,
+    };
+    let stat = match self {
+      Model::DMG0 => 0x81,
+      _ => 0x85,
+    };
+    let nr52 = match self {
+      Model::SGB | Model::SGB2 => 0xF0,
+      _ => 0xF1,
+    };
+    let sc = match self {
+      Model::CGB => 0x7F,
+      _ => 0x7E,
+    };
+    let dma = match self {
+      Model::CGB => 0x00,
+      _ => 0xFF,
+    };
+    vec!(
+      (0xFF00, 0xCF), // P1
+      (0xFF01, 0x00), // SB
+      (0xFF02, sc),   // SC
+      (0xFF04, div),  // DIV
+      (0xFF05, 0x00), // TIMA
+      (0xFF06, 0x00), // TMA
+      (0xFF07, 0xF8), // TAC
+      (0xFF0F, 0xE1), // IF
+      (0xFF10, 0x80), // NR10
+      (0xFF11, 0xBF), // NR11
+      (0xFF12, 0xF3), // NR12
+      (0xFF13, 0xFF), // NR13
+      (0xFF14, 0xBF), // NR14
+      (0xFF16, 0x3F), // NR21
+      (0xFF17, 0x00), // NR22
+      (0xFF18, 0xFF), // NR23
+      (0xFF19, 0xBF), // NR24
+      (0xFF1A, 0x7F), // NR30
+      (0xFF1B, 0xFF), // NR31
+      (0xFF1C, 0x9F), // NR32
+      (0xFF1D, 0xFF), // NR33
+      (0xFF1E, 0xBF), // NR34
+      (0xFF20, 0xFF), // NR41
+      (0xFF21, 0x00), // NR42
+      (0xFF22, 0x00), // NR43
+      (0xFF23, 0xBF), // NR44
+      (0xFF24, 0x77), // NR50
+      (0xFF25, 0xF3), // NR51
+      (0xFF26, nr52), // NR52
+      (0xFF40, 0x91), // LCDC
+      (0xFF41, stat), // STAT
+      (0xFF42, 0x00), // SCY
+      (0xFF43, 0x00), // SCX
+      (0xFF44, 0x00), // LY
+      (0xFF45, 0x00), // LYC
+      (0xFF46, dma),  // DMA
+      (0xFF47, 0xFC), // BGP
+      (0xFF48, 0xFF), // OBP0
+      (0xFF49, 0xFF), // OBP1
+      (0xFF4A, 0x00), // WY
+      (0xFF4B, 0x00), // WX
+      (0xFFFF, 0x00), // IE
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reg::{Flag, RegCode};
+
+    #[test]
+    fn dmg_flags_depend_on_header_checksum() {
+      let zero = Model::DMG.post_boot_registers(0x00);
+      assert_eq!(zero.get_16bit(&RegCode::AF), 0x0180);
+      let non_zero = Model::DMG.post_boot_registers(0x66);
+      assert_eq!(non_zero.get_16bit(&RegCode::AF), 0x01B0);
+      assert!(non_zero.check_flag(&Flag::CarryFlag));
+      assert_eq!(non_zero.get_16bit(&RegCode::HL), 0x014D);
+      assert_eq!(non_zero.pc, 0x0100);
+    }
+
+    #[test]
+    fn parses_model_names() {
+      assert_eq!(Model::from_name("CGB"), Some(Model::CGB));
+      assert_eq!(Model::from_name("sgb2"), Some(Model::SGB2));
+      assert_eq!(Model::from_name("gba"), None);
+    }
+}
@@ -0,0 +1,373 @@
+use crate::cpu::interrupt::{Interrupt, InterruptController};
+use crate::mmu::{OAM_SIZE, VRAM_SIZE};
+use crate::ppu::fifo::Fifo;
+use crate::ppu::sprite::{Sprite, MAX_SPRITES_PER_LINE};
+
+/// Dots (T-cycles) needed to go through one scanline
+pub const DOTS_PER_LINE: u16 = 456;
+pub const OAM_SCAN_DOTS: u16 = 80;
+/// Mode 3 length without any SCX, window or sprite penalty
+pub const DRAWING_DOTS: u16 = 172;
+pub const VISIBLE_LINES: u8 = 144;
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = VISIBLE_LINES as usize;
+pub const LINES_PER_FRAME: u8 = 154;
+
+pub const LCDC_ENABLE: u8 = 0x80;
+pub const LCDC_WINDOW_MAP: u8 = 0x40;
+pub const LCDC_WINDOW_ENABLE: u8 = 0x20;
+pub const LCDC_TILE_DATA: u8 = 0x10;
+pub const LCDC_BG_MAP: u8 = 0x08;
+pub const LCDC_OBJ_SIZE: u8 = 0x04;
+pub const LCDC_OBJ_ENABLE: u8 = 0x02;
+pub const LCDC_BG_ENABLE: u8 = 0x01;
+const STAT_HBLANK_INT: u8 = 0x08;
+const STAT_VBLANK_INT: u8 = 0x10;
+const STAT_OAM_INT: u8 = 0x20;
+const STAT_LYC_INT: u8 = 0x40;
+const STAT_COINCIDENCE: u8 = 0x04;
+
+/// Value of the two low STAT bits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+  HBlank = 0,
+  VBlank = 1,
+  OamScan = 2,
+  Drawing = 3,
+}
+
+/// How pixels are produced
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Renderer {
+  /// Draws a whole line when mode 3 ends, which always lasts 172 dots
+  Scanline,
+  /// Pushes one pixel per dot through the background and object FIFOs. Slower,
+  /// but mid-line register writes land where they should and mode 3 gets
+  /// longer with SCX fine scroll, the window and objects.
+  Fifo,
+}
+
+impl Renderer {
+  pub fn from_name(name: &str) -> Option<Renderer> {
+    return match name.to_lowercase().as_str() {
+      "scanline" => Some(Renderer::Scanline),
+      "fifo" => Some(Renderer::Fifo),
+      _ => None,
+    }
+  }
+}
+
+pub struct Ppu {
+  pub vram: [u8; VRAM_SIZE],
+  pub oam: [u8; OAM_SIZE],
+  pub lcdc: u8,
+  /// Only the writable interrupt select bits, 3-6
+  stat: u8,
+  pub scy: u8,
+  pub scx: u8,
+  pub ly: u8,
+  pub lyc: u8,
+  pub bgp: u8,
+  pub obp0: u8,
+  pub obp1: u8,
+  pub wy: u8,
+  pub wx: u8,
+  mode: Mode,
+  /// Dot within the current scanline
+  dot: u16,
+  /// OR of every enabled STAT source. The interrupt fires on its rising edge only.
+  stat_line: bool,
+  /// Shade (0 white - 3 black) of every pixel, row by row
+  pub framebuffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+  /// Set when a frame is complete, cleared by `take_frame`
+  frame_ready: bool,
+  /// Window row to draw next. Only advances on lines where the window was drawn.
+  pub(super) window_line: u8,
+  /// LY matched WY at some point this frame
+  pub(super) window_triggered: bool,
+  /// Objects picked by the last OAM scan, in OAM order
+  pub(super) line_sprites: Vec<Sprite>,
+  renderer: Renderer,
+  pub(super) fifo: Fifo,
+}
+
+impl Ppu {
+  pub fn new() -> Self {
+    return Ppu::with_renderer(Renderer::Scanline);
+  }
+
+  pub fn with_renderer(renderer: Renderer) -> Self {
+    Ppu {
+      vram: [0; VRAM_SIZE],
+      oam: [0; OAM_SIZE],
+      lcdc: 0x00,
+      stat: 0x00,
+      scy: 0x00,
+      scx: 0x00,
+      ly: 0x00,
+      lyc: 0x00,
+      bgp: 0x00,
+      obp0: 0x00,
+      obp1: 0x00,
+      wy: 0x00,
+      wx: 0x00,
+      mode: Mode::HBlank,
+      dot: 0,
+      stat_line: false,
+      framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+      frame_ready: false,
+      window_line: 0,
+      window_triggered: false,
+      line_sprites: Vec::with_capacity(MAX_SPRITES_PER_LINE),
+      renderer,
+      fifo: Fifo::new(),
+    }
+  }
+
+  /// True once per completed frame
+  pub fn take_frame(&mut self) -> bool {
+    let ready = self.frame_ready;
+    self.frame_ready = false;
+    return ready;
+  }
+
+  pub fn mode(&self) -> Mode {
+    return self.mode;
+  }
+
+  pub fn lcd_enabled(&self) -> bool {
+    return self.lcdc & LCDC_ENABLE != 0;
+  }
+
+  /// Advances the PPU by `cycles` machine cycles
+  pub fn tick(&mut self, cycles: u8, interrupts: &mut InterruptController) {
+    if !self.lcd_enabled() {
+      return;
+    }
+    for _ in 0..(cycles as u16 * 4) {
+      self.step_dot(interrupts);
+    }
+  }
+
+  pub(super) fn step_dot(&mut self, interrupts: &mut InterruptController) {
+    self.dot += 1;
+    if self.ly < VISIBLE_LINES {
+      if self.dot == OAM_SCAN_DOTS {
+        self.scan_oam();
+        self.mode = Mode::Drawing;
+        if self.renderer == Renderer::Fifo {
+          self.start_drawing();
+        }
+      } else if self.mode == Mode::Drawing {
+        let renderer = self.renderer;
+        let line_done = match renderer {
+          Renderer::Scanline => self.dot == OAM_SCAN_DOTS + DRAWING_DOTS,
+          Renderer::Fifo => self.fifo_dot(),
+        };
+        if line_done {
+          self.mode = Mode::HBlank;
+          if renderer == Renderer::Scanline {
+            self.render_line();
+          }
+        }
+      }
+    }
+
+    if self.dot == DOTS_PER_LINE {
+      self.dot = 0;
+      self.ly = (self.ly + 1) % LINES_PER_FRAME;
+      if self.ly == VISIBLE_LINES {
+        self.mode = Mode::VBlank;
+        self.frame_ready = true;
+        self.window_line = 0;
+        self.window_triggered = false;
+        interrupts.request(Interrupt::VBlank);
+      } else if self.ly < VISIBLE_LINES {
+        self.mode = Mode::OamScan;
+      }
+    }
+    self.update_stat_line(interrupts);
+  }
+
+  fn update_stat_line(&mut self, interrupts: &mut InterruptController) {
+    let line = self.lcd_enabled()
+      && ((self.stat & STAT_LYC_INT != 0 && self.ly == self.lyc)
+        || (self.stat & STAT_HBLANK_INT != 0 && self.mode == Mode::HBlank)
+        || (self.stat & STAT_VBLANK_INT != 0 && self.mode == Mode::VBlank)
+        || (self.stat & STAT_OAM_INT != 0 && self.mode == Mode::OamScan));
+    if line && !self.stat_line {
+      interrupts.request(Interrupt::LcdStat);
+    }
+    self.stat_line = line;
+  }
+
+  fn read_stat(&self) -> u8 {
+    if !self.lcd_enabled() {
+      return 0x80 | self.stat;
+    }
+    let coincidence = if self.ly == self.lyc { STAT_COINCIDENCE } else { 0x00 };
+    return 0x80 | self.stat | coincidence | self.mode as u8;
+  }
+
+  /// Reads 0xFF40-0xFF4B, except DMA at 0xFF46 which the bus owns
+  pub fn read_register(&self, addr: usize) -> u8 {
+    return match addr {
+      0xFF40 => self.lcdc,
+      0xFF41 => self.read_stat(),
+      0xFF42 => self.scy,
+      0xFF43 => self.scx,
+      0xFF44 => self.ly,
+      0xFF45 => self.lyc,
+      0xFF47 => self.bgp,
+      0xFF48 => self.obp0,
+      0xFF49 => self.obp1,
+      0xFF4A => self.wy,
+      0xFF4B => self.wx,
+      _ => 0xFF,
+    }
+  }
+
+  pub fn write_register(&mut self, addr: usize, byte: u8, interrupts: &mut InterruptController) {
+    match addr {
+      0xFF40 => self.write_lcdc(byte),
+      0xFF41 => self.stat = byte & 0x78,
+      // LY is read only
+      0xFF44 => (),
+      _ => self.load_register(addr, byte),
+    }
+    self.update_stat_line(interrupts);
+  }
+
+  /// Sets a register without side effects, used for the post-boot state
+  pub fn load_register(&mut self, addr: usize, byte: u8) {
+    match addr {
+      0xFF40 => self.lcdc = byte,
+      0xFF41 => self.stat = byte & 0x78,
+      0xFF42 => self.scy = byte,
+      0xFF43 => self.scx = byte,
+      0xFF44 => self.ly = byte,
+      0xFF45 => self.lyc = byte,
+      0xFF47 => self.bgp = byte,
+      0xFF48 => self.obp0 = byte,
+      0xFF49 => self.obp1 = byte,
+      0xFF4A => self.wy = byte,
+      0xFF4B => self.wx = byte,
+      _ => (),
+    }
+  }
+
+  fn write_lcdc(&mut self, byte: u8) {
+    let was_enabled = self.lcd_enabled();
+    self.lcdc = byte;
+    match (was_enabled, self.lcd_enabled()) {
+      // Turning the LCD off parks the PPU at the start of line 0
+      (true, false) => {
+        self.ly = 0;
+        self.dot = 0;
+        self.mode = Mode::HBlank;
+        self.window_line = 0;
+        self.window_triggered = false;
+        self.framebuffer = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        self.frame_ready = true;
+      }
+      (false, true) => {
+        self.ly = 0;
+        self.dot = 0;
+        self.mode = Mode::OamScan;
+      }
+      _ => (),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn enabled() -> (Ppu, InterruptController) {
+    let mut ppu = Ppu::new();
+    let mut interrupts = InterruptController::new();
+    ppu.write_register(0xFF40, 0x91, &mut interrupts);
+    interrupts.flag = 0;
+    (ppu, interrupts)
+  }
+
+  /// Runs `dots` T-cycles, which must be a multiple of 4
+  fn run(ppu: &mut Ppu, interrupts: &mut InterruptController, dots: u32) {
+    for _ in 0..dots / 4 {
+      ppu.tick(1, interrupts);
+    }
+  }
+
+  #[test]
+  fn walks_through_the_modes_of_a_line() {
+    let (mut ppu, mut interrupts) = enabled();
+    assert_eq!(ppu.mode(), Mode::OamScan);
+    run(&mut ppu, &mut interrupts, 80);
+    assert_eq!(ppu.mode(), Mode::Drawing);
+    run(&mut ppu, &mut interrupts, 172);
+    assert_eq!(ppu.mode(), Mode::HBlank);
+    assert_eq!(ppu.read_register(0xFF41) & 0x03, 0);
+    run(&mut ppu, &mut interrupts, 204);
+    assert_eq!(ppu.mode(), Mode::OamScan);
+    assert_eq!(ppu.read_register(0xFF44), 1);
+  }
+
+  #[test]
+  fn requests_vblank_at_line_144_and_wraps_after_153() {
+    let (mut ppu, mut interrupts) = enabled();
+    run(&mut ppu, &mut interrupts, 456 * 144 - 4);
+    assert_eq!(interrupts.flag & Interrupt::VBlank.mask(), 0);
+    run(&mut ppu, &mut interrupts, 4);
+    assert_eq!(ppu.ly, 144);
+    assert_eq!(ppu.mode(), Mode::VBlank);
+    assert_ne!(interrupts.flag & Interrupt::VBlank.mask(), 0);
+
+    run(&mut ppu, &mut interrupts, 456 * 10);
+    assert_eq!(ppu.ly, 0);
+    assert_eq!(ppu.mode(), Mode::OamScan);
+  }
+
+  #[test]
+  fn lyc_coincidence_sets_stat_and_interrupts_once() {
+    let (mut ppu, mut interrupts) = enabled();
+    ppu.write_register(0xFF45, 2, &mut interrupts);
+    ppu.write_register(0xFF41, STAT_LYC_INT, &mut interrupts);
+    run(&mut ppu, &mut interrupts, 456 * 2);
+    assert_eq!(ppu.read_register(0xFF41) & STAT_COINCIDENCE, STAT_COINCIDENCE);
+    assert_ne!(interrupts.flag & Interrupt::LcdStat.mask(), 0);
+
+    interrupts.flag = 0;
+    run(&mut ppu, &mut interrupts, 200);
+    assert_eq!(interrupts.flag & Interrupt::LcdStat.mask(), 0);
+  }
+
+  #[test]
+  fn mode_sources_share_one_stat_line() {
+    let (mut ppu, mut interrupts) = enabled();
+    ppu.write_register(0xFF41, STAT_HBLANK_INT | STAT_OAM_INT, &mut interrupts);
+    interrupts.flag = 0;
+    run(&mut ppu, &mut interrupts, 252);
+    assert_ne!(interrupts.flag & Interrupt::LcdStat.mask(), 0);
+
+    // HBlank runs straight into the next OAM scan without a falling edge
+    interrupts.flag = 0;
+    run(&mut ppu, &mut interrupts, 204);
+    assert_eq!(ppu.mode(), Mode::OamScan);
+    assert_eq!(interrupts.flag & Interrupt::LcdStat.mask(), 0);
+  }
+
+  #[test]
+  fn disabling_the_lcd_resets_ly_and_stops_the_clock() {
+    let (mut ppu, mut interrupts) = enabled();
+    run(&mut ppu, &mut interrupts, 456 * 5);
+    ppu.write_register(0xFF40, 0x11, &mut interrupts);
+    assert_eq!(ppu.ly, 0);
+    assert_eq!(ppu.read_register(0xFF41) & 0x03, 0);
+    run(&mut ppu, &mut interrupts, 456 * 5);
+    assert_eq!(ppu.ly, 0);
+
+    ppu.write_register(0xFF44, 0x42, &mut interrupts);
+    assert_eq!(ppu.ly, 0);
+  }
+}
@@ -0,0 +1,399 @@
+use std::collections::VecDeque;
+
+use crate::ppu::core::{Ppu, LCDC_BG_MAP, LCDC_OBJ_ENABLE, LCDC_WINDOW_ENABLE, LCDC_WINDOW_MAP, SCREEN_WIDTH};
+use crate::ppu::scanline::{pixel, tile_address};
+use crate::ppu::sprite::ObjPixel;
+
+/// Dots lost at the start of mode 3 to the first tile fetch, which is thrown away
+const STARTUP_DOTS: u8 = 6;
+/// Dots an object fetch stalls the pixel pipeline for, once the background
+/// fetcher has finished its current tile
+const SPRITE_FETCH_DOTS: u8 = 6;
+/// Last WX value at which the window can still show up
+const WX_MAX: u8 = 166;
+
+/// Background fetcher steps. Every step but `Push` takes two dots.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Step {
+  Tile,
+  DataLow,
+  DataHigh,
+  /// Waits for the background FIFO to empty before pushing the fetched row
+  Push,
+}
+
+/// State of the pixel pipeline during mode 3
+pub struct Fifo {
+  background: VecDeque<u8>,
+  /// Aligned with the LCD: the front entry goes with the next pixel sent out
+  objects: VecDeque<Option<ObjPixel>>,
+  step: Step,
+  step_dots: u8,
+  tile: u8,
+  low: u8,
+  high: u8,
+  /// Tiles fetched this line, in the background and in the window
+  tile_x: u8,
+  window_tile_x: u8,
+  in_window: bool,
+  window_drawn: bool,
+  /// Screen column of the next pixel sent to the LCD
+  lx: u8,
+  /// Pixels still to drop for SCX fine scroll, or a window left of column 0
+  discard: u8,
+  startup: u8,
+  /// Object being fetched, as an index into the line's sprites, and dots left
+  sprite_fetch: Option<(usize, u8)>,
+  /// Bit per entry of the line's sprites already fetched
+  fetched_sprites: u16,
+}
+
+impl Fifo {
+  pub fn new() -> Self {
+    Fifo {
+      background: VecDeque::with_capacity(8),
+      objects: VecDeque::with_capacity(8),
+      step: Step::Tile,
+      step_dots: 0,
+      tile: 0,
+      low: 0,
+      high: 0,
+      tile_x: 0,
+      window_tile_x: 0,
+      in_window: false,
+      window_drawn: false,
+      lx: 0,
+      discard: 0,
+      startup: STARTUP_DOTS,
+      sprite_fetch: None,
+      fetched_sprites: 0,
+    }
+  }
+
+  fn restart_fetch(&mut self) {
+    self.step = Step::Tile;
+    self.step_dots = 0;
+  }
+}
+
+impl Ppu {
+  /// Resets the pipeline at the start of mode 3. The fine scroll is latched
+  /// here, so later SCX writes only move the line by whole tiles.
+  pub(super) fn start_drawing(&mut self) {
+    if self.ly == self.wy {
+      self.window_triggered = true;
+    }
+    self.fifo = Fifo::new();
+    self.fifo.discard = self.scx % 8;
+  }
+
+  /// Runs one dot of mode 3. Returns true once the last pixel of the line is
+  /// out, which is what makes the mode last 172 dots or more.
+  pub(super) fn fifo_dot(&mut self) -> bool {
+    if self.fifo.startup > 0 {
+      self.fifo.startup -= 1;
+      return false;
+    }
+    match self.fifo.sprite_fetch {
+      Some((slot, dots)) if dots > 1 => {
+        self.fifo.sprite_fetch = Some((slot, dots - 1));
+        return false;
+      }
+      Some((slot, _)) => {
+        self.fifo.sprite_fetch = None;
+        self.fetch_sprite(slot);
+      }
+      None => self.advance_fetcher(),
+    }
+
+    if self.fifo.discard == 0 && self.window_starts() {
+      self.fifo.background.clear();
+      self.fifo.restart_fetch();
+      self.fifo.in_window = true;
+      self.fifo.window_drawn = true;
+      self.fifo.discard = 7u8.saturating_sub(self.wx);
+      // The window fetch already starts on this dot
+      self.advance_fetcher();
+      return false;
+    }
+    if self.fifo.discard == 0 {
+      if let Some(slot) = self.next_sprite() {
+        if self.fifo.step == Step::Push && !self.fifo.background.is_empty() {
+          self.fifo.sprite_fetch = Some((slot, SPRITE_FETCH_DOTS));
+        }
+        return false;
+      }
+    }
+
+    let color = match self.fifo.background.pop_front() {
+      Some(color) => color,
+      None => return false,
+    };
+    if self.fifo.discard > 0 {
+      self.fifo.discard -= 1;
+      return false;
+    }
+    let object = self.fifo.objects.pop_front().flatten();
+    let object = if self.lcdc & LCDC_OBJ_ENABLE != 0 { object } else { None };
+    let x = self.fifo.lx as usize;
+    self.framebuffer[self.ly as usize * SCREEN_WIDTH + x] = self.mix(color, object);
+    self.fifo.lx += 1;
+
+    if self.fifo.lx as usize == SCREEN_WIDTH {
+      if self.fifo.window_drawn {
+        self.window_line += 1;
+      }
+      return true;
+    }
+    return false;
+  }
+
+  fn window_starts(&self) -> bool {
+    return !self.fifo.in_window
+      && self.window_triggered
+      && self.lcdc & LCDC_WINDOW_ENABLE != 0
+      && self.wx <= WX_MAX
+      && self.fifo.lx as u16 + 7 >= self.wx as u16;
+  }
+
+  fn advance_fetcher(&mut self) {
+    if self.fifo.step == Step::Push {
+      if !self.fifo.background.is_empty() {
+        return;
+      }
+      for x in 0..8 {
+        self.fifo.background.push_back(pixel(self.fifo.low, self.fifo.high, x));
+      }
+      if self.fifo.in_window {
+        self.fifo.window_tile_x += 1;
+      } else {
+        self.fifo.tile_x += 1;
+      }
+      // The next fetch starts on the same dot
+      self.fifo.restart_fetch();
+    }
+
+    self.fifo.step_dots += 1;
+    if self.fifo.step_dots < 2 {
+      return;
+    }
+    self.fifo.step_dots = 0;
+    match self.fifo.step {
+      Step::Tile => {
+        // Clearing the window enable bit mid-line falls back to the background
+        if self.fifo.in_window && self.lcdc & LCDC_WINDOW_ENABLE == 0 {
+          self.fifo.in_window = false;
+        }
+        self.fifo.tile = self.vram[self.fetcher_map_address()];
+        self.fifo.step = Step::DataLow;
+      }
+      Step::DataLow => {
+        self.fifo.low = self.vram[self.fetcher_row_address()];
+        self.fifo.step = Step::DataHigh;
+      }
+      Step::DataHigh => {
+        self.fifo.high = self.vram[self.fetcher_row_address() + 1];
+        self.fifo.step = Step::Push;
+      }
+      Step::Push => (),
+    }
+  }
+
+  /// Line of the 256x256 plane the fetcher reads from. SCY is read on every
+  /// fetch, so it can change mid-line too.
+  fn fetcher_y(&self) -> u8 {
+    if self.fifo.in_window {
+      return self.window_line;
+    }
+    return self.ly.wrapping_add(self.scy);
+  }
+
+  fn fetcher_map_address(&self) -> usize {
+    let (map, column) = if self.fifo.in_window {
+      (self.map_base(LCDC_WINDOW_MAP), self.fifo.window_tile_x)
+    } else {
+      (self.map_base(LCDC_BG_MAP), (self.scx / 8).wrapping_add(self.fifo.tile_x))
+    };
+    return map + (self.fetcher_y() as usize / 8) * 32 + (column as usize & 31);
+  }
+
+  fn fetcher_row_address(&self) -> usize {
+    return tile_address(self.lcdc, self.fifo.tile) + (self.fetcher_y() as usize % 8) * 2;
+  }
+
+  /// Object the LCD reached and which wasn't fetched yet, lowest X first
+  fn next_sprite(&self) -> Option<usize> {
+    if self.lcdc & LCDC_OBJ_ENABLE == 0 {
+      return None;
+    }
+    let lx = self.fifo.lx as i16;
+    return self.line_sprites.iter()
+      .enumerate()
+      .filter(|(slot, sprite)| self.fifo.fetched_sprites & (1 << slot) == 0 && sprite.left() <= lx)
+      .min_by_key(|(_, sprite)| (sprite.x, sprite.index))
+      .map(|(slot, _)| slot);
+  }
+
+  /// Merges an object row into the object FIFO. Pixels already there came
+  /// from objects with priority, only transparent ones get replaced.
+  fn fetch_sprite(&mut self, slot: usize) {
+    self.fifo.fetched_sprites |= 1 << slot;
+    let sprite = self.line_sprites[slot];
+    let colors = sprite.row_colors(&self.vram, self.lcdc, self.ly);
+    for (column, color) in colors.iter().enumerate() {
+      let position = sprite.left() + column as i16 - self.fifo.lx as i16;
+      if position < 0 {
+        continue;
+      }
+      let position = position as usize;
+      while self.fifo.objects.len() <= position {
+        self.fifo.objects.push_back(None);
+      }
+      if self.fifo.objects[position].is_none() && *color != 0 {
+        self.fifo.objects[position] = Some(sprite.pixel_info(*color));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cpu::interrupt::InterruptController;
+  use crate::ppu::core::{Mode, Renderer, DOTS_PER_LINE, LINES_PER_FRAME, OAM_SCAN_DOTS};
+
+  fn scene(renderer: Renderer) -> (Ppu, InterruptController) {
+    let mut ppu = Ppu::with_renderer(renderer);
+    let mut interrupts = InterruptController::new();
+    ppu.bgp = 0xE4;
+    ppu.obp0 = 0xE4;
+    ppu.obp1 = 0x1B;
+    ppu.write_register(0xFF40, 0x93, &mut interrupts);
+    (ppu, interrupts)
+  }
+
+  /// Dots mode 3 of the current line lasts, starting from the line's first dot
+  fn drawing_dots(ppu: &mut Ppu, interrupts: &mut InterruptController) -> u16 {
+    for _ in 0..OAM_SCAN_DOTS {
+      ppu.step_dot(interrupts);
+    }
+    assert_eq!(ppu.mode(), Mode::Drawing);
+    let mut dots = 0;
+    while ppu.mode() == Mode::Drawing {
+      ppu.step_dot(interrupts);
+      dots += 1;
+    }
+    for _ in OAM_SCAN_DOTS + dots..DOTS_PER_LINE {
+      ppu.step_dot(interrupts);
+    }
+    dots
+  }
+
+  fn place(ppu: &mut Ppu, index: usize, y: u8, x: u8, tile: u8, flags: u8) {
+    ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[y, x, tile, flags]);
+  }
+
+  #[test]
+  fn mode_3_grows_with_fine_scroll_and_sprites() {
+    let (mut ppu, mut interrupts) = scene(Renderer::Fifo);
+    assert_eq!(drawing_dots(&mut ppu, &mut interrupts), 172);
+
+    ppu.scx = 5;
+    assert_eq!(drawing_dots(&mut ppu, &mut interrupts), 177);
+
+    // An object aligned with a background tile waits for a whole fetch
+    ppu.scx = 0;
+    place(&mut ppu, 0, 16 + 2, 8, 0, 0);
+    assert_eq!(drawing_dots(&mut ppu, &mut interrupts), 172 + 11);
+    // A second one at the same X only pays for its own fetch
+    place(&mut ppu, 1, 16 + 3, 8, 0, 0);
+    assert_eq!(drawing_dots(&mut ppu, &mut interrupts), 172 + 11 + 6);
+
+    // Further into a tile there is less of the background fetch to wait for
+    place(&mut ppu, 1, 0, 0, 0, 0);
+    place(&mut ppu, 0, 16 + 4, 8 + 3, 0, 0);
+    assert_eq!(drawing_dots(&mut ppu, &mut interrupts), 172 + 8);
+
+    // Starting the window throws away the background fetch in progress
+    place(&mut ppu, 0, 0, 0, 0, 0);
+    ppu.lcdc |= LCDC_WINDOW_ENABLE;
+    ppu.wy = ppu.ly;
+    ppu.wx = 7 + 80;
+    assert_eq!(drawing_dots(&mut ppu, &mut interrupts), 172 + 6);
+    // Once per line, even with WX on the last column
+    ppu.wx = WX_MAX;
+    assert_eq!(drawing_dots(&mut ppu, &mut interrupts), 172 + 6);
+
+    // The scanline renderer always takes the same time
+    let (mut ppu, mut interrupts) = scene(Renderer::Scanline);
+    ppu.scx = 5;
+    assert_eq!(drawing_dots(&mut ppu, &mut interrupts), 172);
+  }
+
+  #[test]
+  fn draws_the_same_frame_as_the_scanline_renderer() {
+    let frame = |renderer| {
+      let (mut ppu, mut interrupts) = scene(renderer);
+      ppu.lcdc |= LCDC_WINDOW_ENABLE | LCDC_WINDOW_MAP;
+      for (i, byte) in ppu.vram[0..0x800].iter_mut().enumerate() {
+        *byte = (i * 7 % 251) as u8;
+      }
+      for (i, byte) in ppu.vram[0x1800..].iter_mut().enumerate() {
+        *byte = (i * 13 % 128) as u8;
+      }
+      ppu.scx = 13;
+      ppu.scy = 200;
+      ppu.wy = 90;
+      ppu.wx = 50;
+      for i in 0..40 {
+        place(&mut ppu, i, (i * 37 % 170) as u8, (i * 29 % 175) as u8, i as u8, (i * 0x30) as u8 & 0xF0);
+      }
+      for _ in 0..DOTS_PER_LINE as u32 * LINES_PER_FRAME as u32 {
+        ppu.step_dot(&mut interrupts);
+      }
+      ppu.framebuffer.to_vec()
+    };
+    assert_eq!(frame(Renderer::Fifo), frame(Renderer::Scanline));
+  }
+
+  #[test]
+  fn coarse_scroll_changes_mid_line() {
+    let (mut ppu, mut interrupts) = scene(Renderer::Fifo);
+    ppu.vram[0x10..0x20].copy_from_slice(&[0xFF; 16]);
+    for column in (0..4).chain(20..32) {
+      ppu.vram[0x1800 + column] = 1;
+    }
+    while ppu.fifo.lx < 40 {
+      ppu.step_dot(&mut interrupts);
+    }
+    ppu.write_register(0xFF43, 64, &mut interrupts);
+    while ppu.mode() == Mode::Drawing {
+      ppu.step_dot(&mut interrupts);
+    }
+    assert_eq!(ppu.framebuffer[10], 3);
+    assert_eq!(ppu.framebuffer[60], 0);
+    assert_eq!(ppu.framebuffer[100], 3);
+  }
+
+  #[test]
+  fn window_can_be_turned_off_mid_line() {
+    let (mut ppu, mut interrupts) = scene(Renderer::Fifo);
+    ppu.lcdc |= LCDC_WINDOW_ENABLE | LCDC_WINDOW_MAP;
+    ppu.vram[0x10..0x20].copy_from_slice(&[0xFF; 16]);
+    for column in 0..32 {
+      ppu.vram[0x1C00 + column] = 1;
+    }
+    ppu.wx = 7;
+    while ppu.fifo.lx < 40 {
+      ppu.step_dot(&mut interrupts);
+    }
+    ppu.lcdc &= !LCDC_WINDOW_ENABLE;
+    while ppu.mode() == Mode::Drawing {
+      ppu.step_dot(&mut interrupts);
+    }
+    assert_eq!(ppu.framebuffer[0], 3);
+    assert_eq!(ppu.framebuffer[39], 3);
+    assert_eq!(ppu.framebuffer[100], 0);
+    assert_eq!(ppu.window_line, 1);
+  }
+}
@@ -0,0 +1,4 @@
+pub mod core;
+pub mod fifo;
+pub mod scanline;
+pub mod sprite;
@@ -0,0 +1,197 @@
+use crate::ppu::core::{
+  Ppu, LCDC_BG_ENABLE, LCDC_BG_MAP, LCDC_TILE_DATA, LCDC_WINDOW_ENABLE, LCDC_WINDOW_MAP, SCREEN_WIDTH,
+};
+
+/// Tile maps as offsets into VRAM
+const MAP_0: usize = 0x1800;
+const MAP_1: usize = 0x1C00;
+/// Window is drawn from screen column WX - 7
+const WINDOW_X_OFFSET: u8 = 7;
+
+/// Offset in VRAM of a background/window tile. With LCDC bit 4 set tiles are
+/// numbered 0-255 from 0x8000, otherwise -128-127 around 0x9000.
+pub fn tile_address(lcdc: u8, tile: u8) -> usize {
+  if lcdc & LCDC_TILE_DATA != 0 {
+    return tile as usize * 16;
+  }
+  return (0x1000 + (tile as i8 as isize) * 16) as usize;
+}
+
+/// Color index (0-3) of column `x` of the two bitplanes of a tile row
+pub fn pixel(low: u8, high: u8, x: u8) -> u8 {
+  let bit = 7 - x;
+  return ((high >> bit) & 1) << 1 | ((low >> bit) & 1);
+}
+
+/// Shade a palette register gives to a color index
+pub fn shade(palette: u8, index: u8) -> u8 {
+  return (palette >> (index * 2)) & 0x03;
+}
+
+impl Ppu {
+  pub(super) fn map_base(&self, select_bit: u8) -> usize {
+    return if self.lcdc & select_bit != 0 { MAP_1 } else { MAP_0 };
+  }
+
+  /// Color index of pixel (`x`, `y`) of the 256x256 plane described by `map`
+  fn map_pixel(&self, map: usize, x: u8, y: u8) -> u8 {
+    let tile = self.vram[map + (y as usize / 8) * 32 + x as usize / 8];
+    let row = tile_address(self.lcdc, tile) + (y as usize % 8) * 2;
+    return pixel(self.vram[row], self.vram[row + 1], x % 8);
+  }
+
+  /// Background and window color indices of the current line, before BGP
+  pub(super) fn background_line(&mut self) -> [u8; SCREEN_WIDTH] {
+    let mut line = [0; SCREEN_WIDTH];
+    if self.ly == self.wy {
+      self.window_triggered = true;
+    }
+    // On DMG bit 0 blanks both layers
+    if self.lcdc & LCDC_BG_ENABLE == 0 {
+      return line;
+    }
+
+    let map = self.map_base(LCDC_BG_MAP);
+    let y = self.ly.wrapping_add(self.scy);
+    for (x, index) in line.iter_mut().enumerate() {
+      *index = self.map_pixel(map, (x as u8).wrapping_add(self.scx), y);
+    }
+
+    let window_visible = self.lcdc & LCDC_WINDOW_ENABLE != 0
+      && self.window_triggered
+      && self.wx < SCREEN_WIDTH as u8 + WINDOW_X_OFFSET;
+    if window_visible {
+      let map = self.map_base(LCDC_WINDOW_MAP);
+      let start = self.wx.saturating_sub(WINDOW_X_OFFSET) as usize;
+      for x in start..SCREEN_WIDTH {
+        let window_x = (x + WINDOW_X_OFFSET as usize - self.wx as usize) as u8;
+        line[x] = self.map_pixel(map, window_x, self.window_line);
+      }
+      self.window_line += 1;
+    }
+    return line;
+  }
+
+  /// Draws the current line, background and objects, into the framebuffer
+  pub(super) fn render_line(&mut self) {
+    let background = self.background_line();
+    let objects = self.sprite_line();
+    let row = self.ly as usize * SCREEN_WIDTH;
+    for x in 0..SCREEN_WIDTH {
+      self.framebuffer[row + x] = self.mix(background[x], objects[x]);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Tile whose every row has color index `index`
+  fn solid_tile(ppu: &mut Ppu, address: usize, index: u8) {
+    for row in 0..8 {
+      ppu.vram[address + row * 2] = if index & 1 != 0 { 0xFF } else { 0x00 };
+      ppu.vram[address + row * 2 + 1] = if index & 2 != 0 { 0xFF } else { 0x00 };
+    }
+  }
+
+  fn render(ppu: &mut Ppu, ly: u8) -> Vec<u8> {
+    ppu.ly = ly;
+    ppu.render_line();
+    let row = ly as usize * SCREEN_WIDTH;
+    ppu.framebuffer[row..row + SCREEN_WIDTH].to_vec()
+  }
+
+  #[test]
+  fn decodes_bitplanes_and_palettes() {
+    assert_eq!(pixel(0b1000_0000, 0b1000_0000, 0), 3);
+    assert_eq!(pixel(0b0100_0000, 0b0000_0000, 1), 1);
+    assert_eq!(pixel(0b0000_0000, 0b0000_0001, 7), 2);
+    assert_eq!(shade(0xE4, 2), 2);
+    assert_eq!(shade(0x1B, 0), 3);
+  }
+
+  #[test]
+  fn addresses_tiles_in_both_modes() {
+    assert_eq!(tile_address(LCDC_TILE_DATA, 0x00), 0x0000);
+    assert_eq!(tile_address(LCDC_TILE_DATA, 0xFF), 0x0FF0);
+    assert_eq!(tile_address(0x00, 0x00), 0x1000);
+    assert_eq!(tile_address(0x00, 0x7F), 0x17F0);
+    assert_eq!(tile_address(0x00, 0x80), 0x0800);
+  }
+
+  #[test]
+  fn renders_scrolled_background_through_bgp() {
+    let mut ppu = Ppu::new();
+    ppu.lcdc = 0x91;
+    ppu.bgp = 0xE4;
+    solid_tile(&mut ppu, 0x0010, 3);
+    // Tile 1 at map column 1, row 0
+    ppu.vram[MAP_0 + 1] = 1;
+
+    let line = render(&mut ppu, 0);
+    assert_eq!(&line[0..8], &[0; 8]);
+    assert_eq!(&line[8..16], &[3; 8]);
+
+    ppu.scx = 4;
+    let line = render(&mut ppu, 0);
+    assert_eq!(line[3], 0);
+    assert_eq!(line[4], 3);
+
+    // Scrolling wraps around the 256 pixel plane
+    ppu.scx = 0;
+    ppu.scy = 0xFF;
+    assert_eq!(render(&mut ppu, 1)[8], 3);
+    assert_eq!(render(&mut ppu, 0)[8], 0);
+  }
+
+  #[test]
+  fn uses_signed_tile_data_and_high_map() {
+    let mut ppu = Ppu::new();
+    ppu.lcdc = 0x80 | LCDC_BG_MAP | LCDC_BG_ENABLE;
+    ppu.bgp = 0xE4;
+    solid_tile(&mut ppu, 0x1000, 1);
+    solid_tile(&mut ppu, 0x0800, 2);
+    ppu.vram[MAP_1] = 0x00;
+    ppu.vram[MAP_1 + 1] = 0x80;
+
+    let line = render(&mut ppu, 0);
+    assert_eq!(line[0], 1);
+    assert_eq!(line[8], 2);
+  }
+
+  #[test]
+  fn window_keeps_its_own_line_counter() {
+    let mut ppu = Ppu::new();
+    ppu.lcdc = 0x80 | LCDC_WINDOW_MAP | LCDC_WINDOW_ENABLE | LCDC_TILE_DATA | LCDC_BG_ENABLE;
+    ppu.bgp = 0xE4;
+    solid_tile(&mut ppu, 0x0010, 2);
+    solid_tile(&mut ppu, 0x0020, 3);
+    // Window row 0 uses tile 1, row 1 tile 2
+    ppu.vram[MAP_1] = 1;
+    ppu.vram[MAP_1 + 32] = 2;
+    ppu.wy = 2;
+    ppu.wx = 7 + 4;
+
+    assert_eq!(render(&mut ppu, 1)[4], 0);
+    for ly in 2..10 {
+      let line = render(&mut ppu, ly);
+      assert_eq!(line[3], 0);
+      assert_eq!(line[4], 2);
+    }
+
+    // Hiding the window for a few lines pauses the counter
+    ppu.lcdc &= !LCDC_WINDOW_ENABLE;
+    render(&mut ppu, 10);
+    ppu.lcdc |= LCDC_WINDOW_ENABLE;
+    assert_eq!(render(&mut ppu, 11)[4], 3);
+  }
+
+  #[test]
+  fn bg_enable_bit_blanks_the_line() {
+    let mut ppu = Ppu::new();
+    ppu.lcdc = 0x90;
+    ppu.bgp = 0xFF;
+    assert_eq!(render(&mut ppu, 0), vec![0; SCREEN_WIDTH]);
+  }
+}
@@ -0,0 +1,278 @@
+use crate::ppu::core::{Ppu, LCDC_BG_ENABLE, LCDC_OBJ_ENABLE, LCDC_OBJ_SIZE, SCREEN_WIDTH};
+use crate::ppu::scanline::{pixel, shade};
+
+/// Objects the PPU keeps for a single line
+pub const MAX_SPRITES_PER_LINE: usize = 10;
+const OAM_ENTRIES: usize = 40;
+/// OAM Y holds the screen row plus 16, X the screen column plus 8
+const Y_OFFSET: i16 = 16;
+const X_OFFSET: i16 = 8;
+
+const FLAG_BEHIND_BG: u8 = 0x80;
+const FLAG_Y_FLIP: u8 = 0x40;
+const FLAG_X_FLIP: u8 = 0x20;
+const FLAG_PALETTE: u8 = 0x10;
+
+/// One OAM entry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+  pub y: u8,
+  pub x: u8,
+  pub tile: u8,
+  pub flags: u8,
+  /// Position in OAM, breaks ties between objects at the same X
+  pub index: u8,
+}
+
+/// Object pixel that won priority over the other objects at its column
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObjPixel {
+  /// Color index, never 0 since that one is transparent
+  pub color: u8,
+  pub obp1: bool,
+  pub behind_background: bool,
+}
+
+impl Sprite {
+  /// Screen column of the leftmost pixel
+  pub fn left(&self) -> i16 {
+    return self.x as i16 - X_OFFSET;
+  }
+
+  /// Color index of the sprite at screen column `x` of line `ly`, 0 if
+  /// transparent or outside of it
+  pub fn color_at(&self, vram: &[u8], lcdc: u8, ly: u8, x: i16) -> u8 {
+    let column = x - self.left();
+    if column < 0 || column >= 8 {
+      return 0;
+    }
+    return self.row_colors(vram, lcdc, ly)[column as usize];
+  }
+
+  /// Color indices of the sprite row drawn on line `ly`, left to right
+  pub fn row_colors(&self, vram: &[u8], lcdc: u8, ly: u8) -> [u8; 8] {
+    let height = sprite_height(lcdc);
+    // Masked in case the object size changed since the OAM scan
+    let mut row = (ly as i16 + Y_OFFSET - self.y as i16) as u8 & (height - 1);
+    if self.flags & FLAG_Y_FLIP != 0 {
+      row = height - 1 - row;
+    }
+    // Tall sprites ignore bit 0 of the tile number. Objects always use 0x8000 addressing.
+    let tile = if height == 16 { self.tile & 0xFE } else { self.tile };
+    let address = tile as usize * 16 + row as usize * 2;
+    let mut colors = [0; 8];
+    for (column, color) in colors.iter_mut().enumerate() {
+      let column = if self.flags & FLAG_X_FLIP != 0 { 7 - column } else { column };
+      *color = pixel(vram[address], vram[address + 1], column as u8);
+    }
+    return colors;
+  }
+
+  pub fn pixel_info(&self, color: u8) -> ObjPixel {
+    return ObjPixel {
+      color,
+      obp1: self.flags & FLAG_PALETTE != 0,
+      behind_background: self.flags & FLAG_BEHIND_BG != 0,
+    }
+  }
+}
+
+fn sprite_height(lcdc: u8) -> u8 {
+  return if lcdc & LCDC_OBJ_SIZE != 0 { 16 } else { 8 };
+}
+
+impl Ppu {
+  /// Mode 2: picks the first ten objects in OAM order overlapping the current
+  /// line. Their X position doesn't matter, even offscreen ones use a slot.
+  pub(super) fn scan_oam(&mut self) {
+    let height = sprite_height(self.lcdc) as i16;
+    let line = self.ly as i16 + Y_OFFSET;
+    self.line_sprites.clear();
+    for index in 0..OAM_ENTRIES {
+      let entry = &self.oam[index * 4..index * 4 + 4];
+      let y = entry[0] as i16;
+      if line >= y && line < y + height {
+        self.line_sprites.push(Sprite { y: entry[0], x: entry[1], tile: entry[2], flags: entry[3], index: index as u8 });
+        if self.line_sprites.len() == MAX_SPRITES_PER_LINE {
+          break;
+        }
+      }
+    }
+  }
+
+  /// Winning object pixel of every column of the current line. On DMG the
+  /// object with the lowest X wins, then the one earlier in OAM. A
+  /// transparent pixel lets the next object through.
+  pub(super) fn sprite_line(&self) -> [Option<ObjPixel>; SCREEN_WIDTH] {
+    let mut line = [None; SCREEN_WIDTH];
+    if self.lcdc & LCDC_OBJ_ENABLE == 0 {
+      return line;
+    }
+    let mut sprites = self.line_sprites.clone();
+    sprites.sort_by_key(|s| (s.x, s.index));
+    for sprite in sprites.iter() {
+      for column in 0..8 {
+        let x = sprite.left() + column;
+        if x < 0 || x >= SCREEN_WIDTH as i16 || line[x as usize].is_some() {
+          continue;
+        }
+        let color = sprite.color_at(&self.vram, self.lcdc, self.ly, x);
+        if color != 0 {
+          line[x as usize] = Some(sprite.pixel_info(color));
+        }
+      }
+    }
+    return line;
+  }
+
+  /// Shade of a pixel once background and object are combined
+  pub(super) fn mix(&self, background: u8, object: Option<ObjPixel>) -> u8 {
+    let bg_shown = self.lcdc & LCDC_BG_ENABLE != 0;
+    if let Some(obj) = object {
+      if !(obj.behind_background && bg_shown && background != 0) {
+        return shade(if obj.obp1 { self.obp1 } else { self.obp0 }, obj.color);
+      }
+    }
+    return if bg_shown { shade(self.bgp, background) } else { 0 };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ppu::core::{LCDC_ENABLE, LCDC_TILE_DATA};
+
+  fn ppu() -> Ppu {
+    let mut ppu = Ppu::new();
+    ppu.lcdc = LCDC_ENABLE | LCDC_TILE_DATA | LCDC_OBJ_ENABLE | LCDC_BG_ENABLE;
+    ppu.bgp = 0xE4;
+    ppu.obp0 = 0xE4;
+    ppu.obp1 = 0x1B;
+    ppu
+  }
+
+  fn place(ppu: &mut Ppu, index: usize, y: u8, x: u8, tile: u8, flags: u8) {
+    ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[y, x, tile, flags]);
+  }
+
+  /// Tile whose rows come in pairs of color 0, 1, 2 then 3,
+  /// and its leftmost column transparent
+  fn striped_tile(ppu: &mut Ppu, tile: usize) {
+    for row in 0..8 {
+      let index = (row / 2) as u8;
+      ppu.vram[tile * 16 + row * 2] = if index & 1 != 0 { 0x7F } else { 0x00 };
+      ppu.vram[tile * 16 + row * 2 + 1] = if index & 2 != 0 { 0x7F } else { 0x00 };
+    }
+  }
+
+  fn render(ppu: &mut Ppu, ly: u8) -> Vec<u8> {
+    ppu.ly = ly;
+    ppu.scan_oam();
+    ppu.render_line();
+    let row = ly as usize * SCREEN_WIDTH;
+    ppu.framebuffer[row..row + SCREEN_WIDTH].to_vec()
+  }
+
+  #[test]
+  fn draws_with_flips_and_palettes() {
+    let mut ppu = ppu();
+    striped_tile(&mut ppu, 1);
+    place(&mut ppu, 0, 16, 8, 1, 0);
+    place(&mut ppu, 1, 16, 20, 1, FLAG_X_FLIP | FLAG_Y_FLIP | FLAG_PALETTE);
+
+    let line = render(&mut ppu, 2);
+    assert_eq!(line[0], 0);
+    assert_eq!(line[1], 1);
+    // Flipped vertically row 2 reads row 5, color 2, through OBP1
+    assert_eq!(line[12], 1);
+    assert_eq!(line[19], 0);
+
+    ppu.lcdc &= !LCDC_OBJ_ENABLE;
+    assert_eq!(render(&mut ppu, 2)[1], 0);
+  }
+
+  #[test]
+  fn tall_sprites_span_two_tiles() {
+    let mut ppu = ppu();
+    ppu.lcdc |= LCDC_OBJ_SIZE;
+    striped_tile(&mut ppu, 2);
+    striped_tile(&mut ppu, 3);
+    ppu.vram[3 * 16 + 12] = 0x80;
+    ppu.vram[3 * 16 + 13] = 0x80;
+    // Bit 0 of the tile number is ignored
+    place(&mut ppu, 0, 16, 8, 3, 0);
+
+    assert_eq!(render(&mut ppu, 0)[0], 0);
+    assert_eq!(render(&mut ppu, 14)[0], 3);
+    assert_eq!(render(&mut ppu, 16)[0], 0);
+
+    ppu.oam[3] = FLAG_Y_FLIP;
+    assert_eq!(render(&mut ppu, 1)[0], 3);
+
+    // Switching to 8x8 after the OAM scan picked the bottom half
+    ppu.ly = 14;
+    ppu.scan_oam();
+    ppu.lcdc &= !LCDC_OBJ_SIZE;
+    ppu.render_line();
+    assert_eq!(ppu.framebuffer[14 * SCREEN_WIDTH], 0);
+  }
+
+  #[test]
+  fn lower_x_then_oam_index_wins() {
+    let mut ppu = ppu();
+    striped_tile(&mut ppu, 1);
+    ppu.vram[0x20..0x30].copy_from_slice(&[0xFF; 16]);
+    place(&mut ppu, 0, 16, 13, 2, 0);
+    place(&mut ppu, 1, 16, 12, 1, 0);
+    // The striped sprite is further left, so it wins despite its OAM index
+    let line = render(&mut ppu, 2);
+    assert_eq!(line[4], 0);
+    assert_eq!(line[5], 1);
+    assert_eq!(line[12], 3);
+
+    // At the same X the earlier entry wins, except where it is transparent
+    place(&mut ppu, 0, 16, 12, 1, 0);
+    place(&mut ppu, 1, 16, 12, 2, 0);
+    let line = render(&mut ppu, 2);
+    assert_eq!(line[4], 3);
+    assert_eq!(line[5], 1);
+  }
+
+  #[test]
+  fn background_priority_hides_behind_nonzero_colors() {
+    let mut ppu = ppu();
+    ppu.vram[0x20..0x30].copy_from_slice(&[0xFF; 16]);
+    // Background tile 1 has color 1 in its left half
+    for row in 0..8 {
+      ppu.vram[0x10 + row * 2] = 0xF0;
+    }
+    ppu.vram[0x1800] = 1;
+    place(&mut ppu, 0, 16, 8, 2, FLAG_BEHIND_BG);
+
+    let line = render(&mut ppu, 0);
+    assert_eq!(line[0], 1);
+    assert_eq!(line[4], 3);
+
+    // With the background off objects always show
+    ppu.lcdc &= !LCDC_BG_ENABLE;
+    assert_eq!(render(&mut ppu, 0)[0], 3);
+  }
+
+  #[test]
+  fn only_ten_sprites_per_line() {
+    let mut ppu = ppu();
+    ppu.vram[0x10..0x20].copy_from_slice(&[0xFF; 16]);
+    // An offscreen object still takes a slot
+    place(&mut ppu, 0, 16, 0, 1, 0);
+    for i in 1..12 {
+      place(&mut ppu, i, 16, 8 * i as u8, 1, 0);
+    }
+    ppu.ly = 0;
+    ppu.scan_oam();
+    assert_eq!(ppu.line_sprites.len(), MAX_SPRITES_PER_LINE);
+
+    let line = render(&mut ppu, 0);
+    assert_eq!(line[8 * 9 - 8], 3);
+    assert_eq!(line[8 * 10 - 8], 0);
+  }
+}
@@ -38,7 +38,7 @@ pub struct Registers {
 impl Registers {
   pub fn new() -> Self {
       Registers {
-        // Initial GBC Model => @TODO: Check
+        // Overwritten by the boot rom or by Model::post_boot_registers
         a: 0x11,
         f: 0x80,
         b: 0x00,
@@ -100,7 +100,7 @@ impl Registers {
 
   pub fn dec_8bit(&mut self, code: &RegCode) {
     let byte = self.get_8bit(code).wrapping_sub(1);
-    self.set_flag(&Flag::HalfCarryFlagBCD, byte & 0xF == 0);
+    self.set_flag(&Flag::HalfCarryFlagBCD, byte & 0xF == 0xF);
     self.set_flag(&Flag::Zero, byte == 0);
     self.set_flag(&Flag::AddSubBCD, true);
     self.set_8bit(code, byte)
@@ -113,9 +113,6 @@ impl Registers {
 
   pub fn inc_hl(&mut self) {
     let current_value = self.get_16bit(&RegCode::HL).wrapping_add(1);
-    self.set_flag(&Flag::HalfCarryFlagBCD, current_value & 0xFF == 0);
-    self.set_flag(&Flag::Zero, current_value == 0);
-    self.set_flag(&Flag::AddSubBCD, false);
     self.set_16bit(&RegCode::HL, 
       ((current_value & 0xFF00) >> 8) as u8,
        (current_value & 0xFF) as u8);
@@ -124,9 +121,6 @@ impl Registers {
   pub fn dec_hl(&mut self) {
     let current_value = self.get_16bit(&RegCode::HL);
     let subbed_value = current_value.wrapping_sub(1);
-    self.set_flag(&Flag::HalfCarryFlagBCD, subbed_value & 0xFF == 0);
-    self.set_flag(&Flag::Zero, subbed_value == 0);
-    self.set_flag(&Flag::AddSubBCD, true);
     self.set_16bit(&RegCode::HL, 
       ((subbed_value & 0xFF00) >> 8) as u8,
        (subbed_value & 0x00FF) as u8);